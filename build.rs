@@ -1,3 +1,39 @@
 fn main() {
     slint_build::compile("ui/app-window.slint").expect("Slint build failed");
+    export_management_interface_version();
+}
+
+/// Expose the pinned `mullvad-management-interface` revision, and the release it belongs to, as
+/// `MANAGEMENT_INTERFACE_REV` and `MANAGEMENT_INTERFACE_VERSION`.
+///
+/// The revision is the `rev` of the dependency in Cargo.toml, and the release is
+/// `package.metadata.management-interface.version`. Either is `unknown` if it's missing.
+fn export_management_interface_version() {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+
+    let manifest: toml::Table = std::fs::read_to_string("Cargo.toml")
+        .expect("Failed to read Cargo.toml")
+        .parse()
+        .expect("Failed to parse Cargo.toml");
+    let get = |path: &[&str]| {
+        get_str(&manifest, path).unwrap_or_else(|| {
+            println!("cargo:warning={} is not set in Cargo.toml", path.join("."));
+            "unknown"
+        })
+    };
+
+    let rev = get(&["dependencies", "mullvad-management-interface", "rev"]);
+    let version = get(&["package", "metadata", "management-interface", "version"]);
+
+    println!("cargo:rustc-env=MANAGEMENT_INTERFACE_REV={rev}");
+    println!("cargo:rustc-env=MANAGEMENT_INTERFACE_VERSION={version}");
+}
+
+/// The string at `path` in `manifest`, e.g. `["package", "name"]`.
+fn get_str<'a>(manifest: &'a toml::Table, path: &[&str]) -> Option<&'a str> {
+    let (key, tables) = path.split_last()?;
+    let table = tables
+        .iter()
+        .try_fold(manifest, |table, key| table.get(*key)?.as_table())?;
+    table.get(*key)?.as_str()
 }
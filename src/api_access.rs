@@ -0,0 +1,424 @@
+//! API access methods: how the daemon reaches the Mullvad API.
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::Context as _;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::{
+    access_method::{AccessMethod, AccessMethodSetting, BuiltInAccessMethod},
+    settings::Settings,
+};
+use slint::{ComponentHandle as _, Model as _, ModelRc, SharedString, VecModel, Weak};
+use talpid_types::net::proxy::{CustomProxy, Shadowsocks, Socks5Remote, SocksAuth};
+
+use crate::{
+    RT,
+    compat::DaemonFeature,
+    rpc::Rpc,
+    slint_ty::{ApiAccess, ApiAccessForm, ApiAccessMethod, ApiProxyProtocol, AppWindow},
+};
+
+/// Shadowsocks ciphers offered in the UI.
+const SHADOWSOCKS_CIPHERS: &[&str] = &[
+    "aes-128-gcm",
+    "aes-256-gcm",
+    "chacha20-ietf-poly1305",
+    "aes-128-cfb",
+    "aes-256-cfb",
+    "chacha20-ietf",
+    "chacha20",
+];
+
+/// Install callbacks for the API access views.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let rpc = &rpc.for_feature(DaemonFeature::ApiAccess);
+    let api_access = app.global::<ApiAccess>();
+    let ciphers: VecModel<SharedString> = SHADOWSOCKS_CIPHERS.iter().map(|&c| c.into()).collect();
+    api_access.set_ciphers(ModelRc::new(ciphers));
+
+    {
+        let rpc = rpc.clone();
+        api_access.on_set_enabled(move |id, enabled| {
+            update_access_method(&rpc, id, move |method| {
+                method.enabled = enabled;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        api_access.on_use_method(move |id| {
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let method = find_access_method(&mut rpc, &id).await?;
+                rpc.set_access_method(method.get_id()).await?;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        api_access.on_remove_method(move |id| {
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let method = find_access_method(&mut rpc, &id).await?;
+                rpc.remove_access_method(method.get_id()).await?;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        api_access.on_test_method(move |id| {
+            set_test_status(&app_weak, id.clone(), true, None);
+
+            // Not using `spawn_with_rpc`, since the UI must hear about failures to connect too.
+            let rpc = rpc.clone();
+            let app_weak = app_weak.clone();
+            RT.spawn(async move {
+                let result = rpc
+                    .with_rpc({
+                        let id = id.clone();
+                        async move |mut rpc| {
+                            let method = find_access_method(&mut rpc, &id).await?;
+                            Ok(rpc.test_api_access_method_by_id(method.get_id()).await?)
+                        }
+                    })
+                    .await;
+
+                if let Err(e) = &result {
+                    tracing::error!("Failed to test API access method: {e:#}");
+                }
+                set_test_status(&app_weak, id, false, Some(matches!(result, Ok(true))));
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        api_access.on_save_method(move |id, form| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let api_access = app.global::<ApiAccess>();
+
+            let proxy = match parse_form(&form) {
+                Ok(proxy) => proxy,
+                Err(error) => return api_access.set_error(error.into()),
+            };
+            api_access.set_error("".into());
+
+            let name = form.name.trim().to_owned();
+            if id.is_empty() {
+                rpc.spawn_with_rpc(async move |mut rpc| {
+                    rpc.add_access_method(name, true, AccessMethod::Custom(proxy))
+                        .await?;
+                    Ok(())
+                });
+            } else {
+                update_access_method(&rpc, id, move |method| {
+                    method.name = name;
+                    method.access_method = AccessMethod::Custom(proxy);
+                    Ok(())
+                });
+            }
+        });
+    }
+}
+
+/// Sync the API access views with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let api_access = app.global::<ApiAccess>();
+
+    // Keep the outcome of tests across updates.
+    let previous = api_access.get_methods();
+    let methods: VecModel<_> = settings
+        .api_access_methods
+        .iter()
+        .map(|method| {
+            let id = method.get_id().to_string();
+            let previous = previous.iter().find(|previous| previous.id.as_str() == id);
+            ApiAccessMethod {
+                id: id.into(),
+                name: method.name.as_str().into(),
+                description: describe(&method.access_method).into(),
+                builtin: method.is_builtin(),
+                enabled: method.enabled(),
+                form: to_form(method),
+                ..previous.unwrap_or_default()
+            }
+        })
+        .collect();
+    api_access.set_methods(ModelRc::new(methods));
+}
+
+/// Highlight the method that the daemon is currently using.
+pub fn update_current(app_weak: &Weak<AppWindow>, current: &AccessMethodSetting) {
+    let id = current.get_id().to_string();
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        app.global::<ApiAccess>().set_current(id.into());
+    });
+}
+
+/// Fetch the access method with ID `id` from the daemon, apply `f`, and send it back.
+fn update_access_method<F>(rpc: &Rpc, id: SharedString, f: F)
+where
+    F: FnOnce(&mut AccessMethodSetting) -> anyhow::Result<()> + Send + 'static,
+{
+    rpc.spawn_with_rpc(async move |mut rpc| {
+        let mut method = find_access_method(&mut rpc, &id).await?;
+        f(&mut method)?;
+        rpc.update_access_method(method).await?;
+        Ok(())
+    });
+}
+
+async fn find_access_method(
+    rpc: &mut MullvadProxyClient,
+    id: &str,
+) -> anyhow::Result<AccessMethodSetting> {
+    rpc.get_api_access_methods()
+        .await?
+        .into_iter()
+        .find(|method| method.get_id().to_string() == id)
+        .with_context(|| format!("No API access method with ID {id}"))
+}
+
+fn set_test_status(
+    app_weak: &Weak<AppWindow>,
+    id: SharedString,
+    testing: bool,
+    succeeded: Option<bool>,
+) {
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let methods = app.global::<ApiAccess>().get_methods();
+        let Some(row) = methods.iter().position(|method| method.id == id) else {
+            return;
+        };
+        let mut method = methods.row_data(row).unwrap_or_default();
+        method.testing = testing;
+        method.test_succeeded = succeeded == Some(true);
+        method.test_result = match succeeded {
+            None => "".into(),
+            Some(true) => "API reachable".into(),
+            Some(false) => "API unreachable".into(),
+        };
+        methods.set_row_data(row, method);
+    });
+}
+
+fn describe(access_method: &AccessMethod) -> String {
+    match access_method {
+        AccessMethod::BuiltIn(BuiltInAccessMethod::Direct) => "Direct".to_owned(),
+        AccessMethod::BuiltIn(BuiltInAccessMethod::Bridge) => "Mullvad Bridges".to_owned(),
+        AccessMethod::BuiltIn(BuiltInAccessMethod::EncryptedDnsProxy) => {
+            "Encrypted DNS proxy".to_owned()
+        }
+        AccessMethod::Custom(CustomProxy::Socks5Remote(socks)) => {
+            format!("SOCKS5 {}", socks.endpoint)
+        }
+        AccessMethod::Custom(CustomProxy::Socks5Local(socks)) => {
+            format!("Local SOCKS5 port {}", socks.local_port)
+        }
+        AccessMethod::Custom(CustomProxy::Shadowsocks(shadowsocks)) => {
+            format!("Shadowsocks {}", shadowsocks.endpoint)
+        }
+    }
+}
+
+/// Fill in the form for editing a custom method. Built-in methods get an empty form.
+fn to_form(method: &AccessMethodSetting) -> ApiAccessForm {
+    let name = method.name.as_str().into();
+    match &method.access_method {
+        AccessMethod::Custom(CustomProxy::Socks5Remote(socks)) => {
+            let (username, password) = socks
+                .auth
+                .as_ref()
+                .map(|auth| (auth.username().into(), auth.password().into()))
+                .unwrap_or_default();
+            ApiAccessForm {
+                name,
+                protocol: ApiProxyProtocol::Socks5,
+                server: socks.endpoint.ip().to_string().into(),
+                port: socks.endpoint.port().to_string().into(),
+                username,
+                password,
+                ..Default::default()
+            }
+        }
+        AccessMethod::Custom(CustomProxy::Shadowsocks(shadowsocks)) => ApiAccessForm {
+            name,
+            protocol: ApiProxyProtocol::Shadowsocks,
+            server: shadowsocks.endpoint.ip().to_string().into(),
+            port: shadowsocks.endpoint.port().to_string().into(),
+            password: shadowsocks.password.as_str().into(),
+            cipher: shadowsocks.cipher.as_str().into(),
+            ..Default::default()
+        },
+        _ => ApiAccessForm {
+            name,
+            ..Default::default()
+        },
+    }
+}
+
+/// Validate a custom method entered by the user.
+fn parse_form(form: &ApiAccessForm) -> Result<CustomProxy, &'static str> {
+    if form.name.trim().is_empty() {
+        return Err("Please enter a name.");
+    }
+    let ip: IpAddr = form
+        .server
+        .trim()
+        .parse()
+        .map_err(|_| "The server must be an IPv4 or IPv6 address.")?;
+    let port: u16 = form
+        .port
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&port| port != 0)
+        .ok_or("The port must be a number between 1 and 65535.")?;
+    let endpoint = SocketAddr::new(ip, port);
+
+    match form.protocol {
+        ApiProxyProtocol::Socks5 => {
+            let proxy = match (form.username.as_str(), form.password.as_str()) {
+                ("", "") => Socks5Remote::new(endpoint),
+                ("", _) | (_, "") => {
+                    return Err("Enter both a username and a password, or neither.");
+                }
+                (username, password) => {
+                    let auth = SocksAuth::new(username.to_owned(), password.to_owned())
+                        .map_err(|_| "The username and password must be 1 to 255 bytes long.")?;
+                    Socks5Remote::new_with_authentication(endpoint, auth)
+                }
+            };
+            Ok(CustomProxy::Socks5Remote(proxy))
+        }
+        ApiProxyProtocol::Shadowsocks => {
+            if !SHADOWSOCKS_CIPHERS.contains(&form.cipher.as_str()) {
+                return Err("Please select a cipher.");
+            }
+            Ok(CustomProxy::Shadowsocks(Shadowsocks::new(
+                endpoint,
+                form.cipher.to_string(),
+                form.password.to_string(),
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socks5() -> ApiAccessForm {
+        ApiAccessForm {
+            name: "Office proxy".into(),
+            protocol: ApiProxyProtocol::Socks5,
+            server: " 192.0.2.1 ".into(),
+            port: "1080".into(),
+            ..ApiAccessForm::default()
+        }
+    }
+
+    fn shadowsocks() -> ApiAccessForm {
+        ApiAccessForm {
+            protocol: ApiProxyProtocol::Shadowsocks,
+            server: "2001:db8::1".into(),
+            port: "443".into(),
+            password: "secret".into(),
+            cipher: "aes-256-gcm".into(),
+            ..socks5()
+        }
+    }
+
+    #[test]
+    fn socks5_without_authentication() {
+        let Ok(CustomProxy::Socks5Remote(proxy)) = parse_form(&socks5()) else {
+            panic!("Expected a SOCKS5 proxy");
+        };
+        assert_eq!(proxy.endpoint, "192.0.2.1:1080".parse().unwrap());
+        assert!(proxy.auth.is_none());
+    }
+
+    #[test]
+    fn socks5_with_authentication() {
+        let form = ApiAccessForm {
+            username: "user".into(),
+            password: "pass".into(),
+            ..socks5()
+        };
+        let Ok(CustomProxy::Socks5Remote(proxy)) = parse_form(&form) else {
+            panic!("Expected a SOCKS5 proxy");
+        };
+        assert!(proxy.auth.is_some());
+    }
+
+    #[test]
+    fn socks5_needs_both_credentials() {
+        let username_only = ApiAccessForm {
+            username: "user".into(),
+            ..socks5()
+        };
+        let password_only = ApiAccessForm {
+            password: "pass".into(),
+            ..socks5()
+        };
+        let error = Some("Enter both a username and a password, or neither.");
+        assert_eq!(parse_form(&username_only).err(), error);
+        assert_eq!(parse_form(&password_only).err(), error);
+    }
+
+    #[test]
+    fn shadowsocks_with_cipher() {
+        let Ok(CustomProxy::Shadowsocks(proxy)) = parse_form(&shadowsocks()) else {
+            panic!("Expected a Shadowsocks proxy");
+        };
+        assert_eq!(proxy.endpoint, "[2001:db8::1]:443".parse().unwrap());
+        assert_eq!(proxy.cipher, "aes-256-gcm");
+        assert_eq!(proxy.password, "secret");
+    }
+
+    #[test]
+    fn shadowsocks_needs_a_known_cipher() {
+        for cipher in ["", "rot13"] {
+            let form = ApiAccessForm {
+                cipher: cipher.into(),
+                ..shadowsocks()
+            };
+            assert_eq!(parse_form(&form).err(), Some("Please select a cipher."));
+        }
+    }
+
+    #[test]
+    fn reject_invalid_fields() {
+        let port_error = Some("The port must be a number between 1 and 65535.");
+        for port in ["", "0", "65536", "http"] {
+            let form = ApiAccessForm {
+                port: port.into(),
+                ..socks5()
+            };
+            assert_eq!(parse_form(&form).err(), port_error);
+        }
+
+        let form = ApiAccessForm {
+            name: " ".into(),
+            ..socks5()
+        };
+        assert_eq!(parse_form(&form).err(), Some("Please enter a name."));
+
+        let form = ApiAccessForm {
+            server: "proxy.example.com".into(),
+            ..socks5()
+        };
+        assert_eq!(
+            parse_form(&form).err(),
+            Some("The server must be an IPv4 or IPv6 address.")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Versions of this app and the daemon, and notices about available updates.
+
+use mullvad_types::version::AppVersionInfo;
+use slint::{ComponentHandle as _, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    RT,
+    compat::{self, Compatibility, DaemonInfo},
+    rpc::Rpc,
+    slint_ty::{AppInfo, AppWindow, Route, View},
+};
+
+/// Version of this app.
+const GUI_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Show the versions that are known at build time, and follow the daemon we're connected to.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let app_info = app.global::<AppInfo>();
+    app_info.set_gui_version(GUI_VERSION.into());
+    app_info.set_interface_rev(compat::INTERFACE_REV.into());
+    app_info.set_interface_version(compat::INTERFACE_VERSION.into());
+    app_info.set_daemon_version("".into());
+    app_info.set_suggested_upgrade("".into());
+    app_info.set_current_version_supported(true);
+
+    let mut daemon = rpc.subscribe_daemon();
+    let app_weak = app.as_weak();
+    RT.spawn(async move {
+        // The daemon version we last warned about, so that the warning isn't repeated.
+        let mut last_warned = None;
+        loop {
+            let info = daemon.borrow_and_update().clone();
+            if let Some(info) = info {
+                let warn = info.compatibility != Compatibility::Compatible
+                    && last_warned.as_ref() != Some(&info.version);
+                if warn {
+                    last_warned = Some(info.version.clone());
+                }
+                update_daemon(&app_weak, info, warn);
+            }
+
+            if daemon.changed().await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Show the version of the running daemon, and whether we can talk to it.
+///
+/// If `warn` is set, the incompatibility screen is opened. It's closed again if we reconnect to a
+/// compatible daemon.
+fn update_daemon(app_weak: &Weak<AppWindow>, daemon: DaemonInfo, warn: bool) {
+    let compatible = daemon.compatibility == Compatibility::Compatible;
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let known_issues: VecModel<SharedString> = daemon
+            .compatibility
+            .known_issues()
+            .iter()
+            .map(|&issue| issue.into())
+            .collect();
+
+        let app_info = app.global::<AppInfo>();
+        app_info.set_daemon_version(daemon.version.into());
+        app_info.set_compatibility(daemon.compatibility.into());
+        app_info.set_known_issues(ModelRc::new(known_issues));
+        app_info.set_rejected_calls(daemon.rejected_calls);
+        app_info.set_unsupported(daemon.unsupported.as_slice().into());
+
+        if warn || compatible {
+            app.global::<Route>()
+                .set_incompatible_service(View { show: warn });
+        }
+    });
+}
+
+/// Show whether an update is available, both in the UI and on the tray icon.
+pub fn update_version_info(app_weak: &Weak<AppWindow>, version_info: &AppVersionInfo) {
+    let supported = version_info.current_version_supported;
+    let suggested_upgrade = version_info
+        .suggested_upgrade
+        .as_ref()
+        .map(|upgrade| upgrade.version.to_string())
+        .unwrap_or_default();
+
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let app_info = app.global::<AppInfo>();
+        app_info.set_current_version_supported(supported);
+        app_info.set_suggested_upgrade(suggested_upgrade.into());
+
+        #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+        update_tray(&app);
+    });
+}
+
+/// Show the update notice on the tray icon too, unless the user has turned it off or dismissed it.
+#[cfg(all(target_os = "linux", feature = "tray-icon"))]
+pub fn update_tray(app: &AppWindow) {
+    crate::tray::set_notification(app.global::<AppInfo>().get_show_update_notice());
+}
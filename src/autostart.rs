@@ -0,0 +1,186 @@
+//! Launch the app on start-up using an XDG autostart entry.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use slint::ComponentHandle as _;
+
+use crate::slint_ty::{AppWindow, State};
+
+/// Used as the name of the autostart entry.
+const APP_ID: &str = "net.mullvad.MullvadSlint";
+
+pub struct Autostart {
+    /// Directory containing autostart entries, e.g. `~/.config/autostart`.
+    dir: PathBuf,
+    /// Set if we're running inside a Flatpak sandbox.
+    flatpak_id: Option<String>,
+}
+
+impl Autostart {
+    /// `config_home` is the XDG config directory, e.g. `~/.config`.
+    pub fn from_env(config_home: &Path) -> Self {
+        Self::new(
+            config_home,
+            env::var("FLATPAK_ID").ok(),
+            env::var_os("HOME").map(PathBuf::from),
+        )
+    }
+
+    /// `flatpak_id` and `home` are the values of `FLATPAK_ID` and `HOME`, if set.
+    fn new(config_home: &Path, flatpak_id: Option<String>, home: Option<PathBuf>) -> Self {
+        // Inside Flatpak, XDG_CONFIG_HOME points into the sandbox, which the session manager
+        // doesn't look at. HOME is still the real home directory though.
+        let config_home = match (&flatpak_id, home) {
+            (Some(_), Some(home)) => home.join(".config"),
+            _ => config_home.to_owned(),
+        };
+
+        Autostart {
+            dir: config_home.join("autostart"),
+            flatpak_id,
+        }
+    }
+
+    fn entry_path(&self) -> PathBuf {
+        self.dir.join(format!("{APP_ID}.desktop"))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.entry_path().is_file()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> io::Result<()> {
+        if !enabled {
+            return match fs::remove_file(self.entry_path()) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            };
+        }
+
+        let exec = match &self.flatpak_id {
+            Some(flatpak_id) => format!("flatpak run {}", quote_exec_arg(flatpak_id)),
+            None => quote_exec_arg(&env::current_exe()?.to_string_lossy()),
+        };
+
+        let entry = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=Mullvad VPN\n\
+             Exec={exec}\n\
+             Icon={APP_ID}\n\
+             Terminal=false\n\
+             X-GNOME-Autostart-enabled=true\n"
+        );
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.entry_path(), entry)
+    }
+}
+
+/// Quote an argument for the `Exec` key of a desktop entry, if necessary.
+fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`',
+    ];
+
+    if !arg.contains(RESERVED) {
+        return arg.to_owned();
+    }
+
+    let mut quoted = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Sync the "Launch app on start-up" toggle with the autostart entry.
+pub fn setup(app: &AppWindow, autostart: Autostart) {
+    let state = app.global::<State>();
+    state.set_launch_on_startup(autostart.is_enabled());
+
+    let app_weak = app.as_weak();
+    state.on_set_launch_on_startup(move |enabled| {
+        if let Err(e) = autostart.set_enabled(enabled) {
+            tracing::error!("Failed to update autostart entry: {e}");
+        }
+
+        // Reflect what actually happened on disk.
+        if let Some(app) = app_weak.upgrade() {
+            app.global::<State>()
+                .set_launch_on_startup(autostart.is_enabled());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_plain_arg() {
+        assert_eq!(
+            quote_exec_arg("/usr/bin/mullvad-slint"),
+            "/usr/bin/mullvad-slint"
+        );
+    }
+
+    #[test]
+    fn quote_reserved_chars() {
+        assert_eq!(quote_exec_arg("/opt/my apps/app"), r#""/opt/my apps/app""#);
+        assert_eq!(quote_exec_arg(r#"a"b`c$d\e"#), r#""a\"b\`c\$d\\e""#);
+    }
+
+    #[test]
+    fn create_and_remove_entry() {
+        let config_home = tempfile::tempdir().unwrap();
+        let autostart = Autostart::new(config_home.path(), None, None);
+        assert!(!autostart.is_enabled());
+
+        autostart.set_enabled(true).unwrap();
+        assert!(autostart.is_enabled());
+        let entry_path = config_home
+            .path()
+            .join("autostart")
+            .join(format!("{APP_ID}.desktop"));
+        let entry = fs::read_to_string(entry_path).unwrap();
+        let exe = env::current_exe().unwrap();
+        assert!(entry.contains(&format!(
+            "Exec={}\n",
+            quote_exec_arg(&exe.to_string_lossy())
+        )));
+
+        autostart.set_enabled(false).unwrap();
+        assert!(!autostart.is_enabled());
+        // Removing it again is not an error.
+        autostart.set_enabled(false).unwrap();
+    }
+
+    #[test]
+    fn flatpak_entry_in_real_home() {
+        let config_home = tempfile::tempdir().unwrap();
+        let home = tempfile::tempdir().unwrap();
+        let autostart = Autostart::new(
+            config_home.path(),
+            Some("net.mullvad.MullvadSlint".to_owned()),
+            Some(home.path().to_owned()),
+        );
+
+        autostart.set_enabled(true).unwrap();
+        let entry_path = home
+            .path()
+            .join(".config/autostart")
+            .join(format!("{APP_ID}.desktop"));
+        let entry = fs::read_to_string(entry_path).unwrap();
+        assert!(entry.contains("Exec=flatpak run net.mullvad.MullvadSlint\n"));
+        assert!(!config_home.path().join("autostart").exists());
+    }
+}
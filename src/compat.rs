@@ -0,0 +1,162 @@
+//! Compatibility between the `mullvad-management-interface` we're built against and the daemon.
+
+use mullvad_management_interface::{Code, Status};
+
+/// Git revision of `mullvad-management-interface` that we're built against. Set by build.rs.
+pub const INTERFACE_REV: &str = env!("MANAGEMENT_INTERFACE_REV");
+
+/// The release that [`INTERFACE_REV`] belongs to, e.g. `2026.1-beta1`. Set by build.rs.
+pub const INTERFACE_VERSION: &str = env!("MANAGEMENT_INTERFACE_VERSION");
+
+/// The daemon we're connected to, as found by the version handshake.
+#[derive(Clone, Debug)]
+pub struct DaemonInfo {
+    pub version: String,
+    pub compatibility: Compatibility,
+    /// Whether the daemon has rejected a call as unimplemented since we connected.
+    pub rejected_calls: bool,
+    /// Features with a call that the daemon has rejected as unimplemented.
+    pub unsupported: Vec<DaemonFeature>,
+}
+
+impl DaemonInfo {
+    pub fn new(version: String) -> Self {
+        let compatibility = Compatibility::check(&version);
+        Self {
+            version,
+            compatibility,
+            rejected_calls: false,
+            unsupported: vec![],
+        }
+    }
+}
+
+/// Features that an older daemon may not implement. Once it rejects one of their calls, their
+/// views are disabled until we connect to another daemon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DaemonFeature {
+    Daita,
+    Obfuscation,
+    ApiAccess,
+    RelayOverrides,
+    SettingsImport,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    Compatible,
+    /// The daemon is from an older release than our interface.
+    DaemonOlder,
+    /// The daemon is from a newer release than our interface.
+    DaemonNewer,
+    /// The daemon version could not be parsed.
+    Unknown,
+}
+
+impl Compatibility {
+    /// Compare a daemon version with [`INTERFACE_VERSION`].
+    ///
+    /// The protocol is not expected to change between betas of the same release, so only the
+    /// release (e.g. `2026.1`) is compared.
+    pub fn check(daemon_version: &str) -> Self {
+        Self::compare(daemon_version, INTERFACE_VERSION)
+    }
+
+    fn compare(daemon_version: &str, interface_version: &str) -> Self {
+        match (release(daemon_version), release(interface_version)) {
+            (Some(daemon), Some(interface)) if daemon == interface => Compatibility::Compatible,
+            (Some(daemon), Some(interface)) if daemon < interface => Compatibility::DaemonOlder,
+            (Some(_), Some(_)) => Compatibility::DaemonNewer,
+            _ => Compatibility::Unknown,
+        }
+    }
+
+    /// What is known to break when talking to a daemon with this compatibility.
+    pub fn known_issues(self) -> &'static [&'static str] {
+        match self {
+            Compatibility::Compatible => &[],
+            Compatibility::DaemonOlder => &[
+                "Settings that were added after the service's release can't be changed.",
+                "Changing a setting the service doesn't know about fails with an \"unimplemented\" error.",
+                "Tunnel features added after the service's release are never shown as active.",
+            ],
+            Compatibility::DaemonNewer => &[
+                "Settings added after this app's release are not shown, and are kept as they are.",
+                "Settings that the service has removed can't be changed.",
+                "Tunnel states or settings this app doesn't understand may make it lose the connection to the service.",
+            ],
+            Compatibility::Unknown => {
+                &["The service version could not be read, so anything may break."]
+            }
+        }
+    }
+}
+
+/// Whether `error` means that the daemon doesn't implement a call, i.e. that it's too old.
+pub fn is_unsupported(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let status = cause
+            .downcast_ref::<Status>()
+            .or_else(|| cause.downcast_ref::<Box<Status>>().map(|status| &**status));
+        status.is_some_and(|status| status.code() == Code::Unimplemented)
+    })
+}
+
+/// Parse the release from a version such as `2026.1-beta1` or `2026.1-dev-abc123`.
+fn release(version: &str) -> Option<(u32, u32)> {
+    let version = version.trim();
+    let release = version
+        .split_once('-')
+        .map_or(version, |(release, _)| release);
+    let (year, number) = release.split_once('.')?;
+    Some((year.parse().ok()?, number.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_release() {
+        assert_eq!(release("2026.1"), Some((2026, 1)));
+        assert_eq!(release("2026.1-beta1"), Some((2026, 1)));
+        assert_eq!(release(" 2025.14-dev-abc123\n"), Some((2025, 14)));
+        assert_eq!(release("2026"), None);
+        assert_eq!(release("unknown"), None);
+        assert_eq!(release(""), None);
+    }
+
+    #[test]
+    fn compare_versions() {
+        let compare = Compatibility::compare;
+        assert_eq!(compare("2026.1", "2026.1-beta1"), Compatibility::Compatible);
+        assert_eq!(
+            compare("2026.1-beta2", "2026.1-beta1"),
+            Compatibility::Compatible
+        );
+        assert_eq!(
+            compare("2025.14", "2026.1-beta1"),
+            Compatibility::DaemonOlder
+        );
+        assert_eq!(compare("2026.0", "2026.1"), Compatibility::DaemonOlder);
+        assert_eq!(
+            compare("2026.2-beta1", "2026.1-beta1"),
+            Compatibility::DaemonNewer
+        );
+        assert_eq!(compare("2027.1", "2026.1"), Compatibility::DaemonNewer);
+        assert_eq!(compare("garbage", "2026.1-beta1"), Compatibility::Unknown);
+        assert_eq!(compare("2026.1", "unknown"), Compatibility::Unknown);
+    }
+
+    #[test]
+    fn detect_unimplemented() {
+        let unimplemented = anyhow::Error::new(Status::unimplemented("SetDaitaSettings"));
+        assert!(is_unsupported(
+            &unimplemented.context("Failed to enable DAITA")
+        ));
+
+        let unavailable = anyhow::Error::new(Status::unavailable("daemon is gone"));
+        assert!(!is_unsupported(&unavailable));
+        assert!(!is_unsupported(&anyhow::anyhow!("unimplemented")));
+    }
+}
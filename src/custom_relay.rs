@@ -0,0 +1,249 @@
+//! Custom WireGuard servers, used instead of the Mullvad relays.
+
+use std::net::IpAddr;
+
+use anyhow::bail;
+use mullvad_types::{
+    ConnectionConfig,
+    custom_tunnel::CustomTunnelEndpoint,
+    relay_constraints::{RelayConstraints, RelaySettings},
+    settings::Settings,
+};
+use slint::ComponentHandle as _;
+use talpid_types::net::wireguard::PublicKey;
+
+use crate::{
+    enforce,
+    profiles::Field,
+    rpc::Rpc,
+    slint_ty::{AppWindow, CustomEndpoint, CustomRelay},
+};
+
+/// A validated [`CustomEndpoint`].
+struct Edit {
+    host: String,
+    port: u16,
+    peer_public_key: PublicKey,
+    addresses: Vec<IpAddr>,
+}
+
+/// Install callbacks for the custom server view.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let custom_relay = app.global::<CustomRelay>();
+
+    {
+        let rpc = rpc.clone();
+        custom_relay.on_use_mullvad_relays(move || {
+            // Switching resets the location.
+            if let Err(e) = enforce::check(Field::Location) {
+                tracing::warn!("{e}");
+                return;
+            }
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                rpc.set_relay_settings(RelaySettings::Normal(RelayConstraints::default()))
+                    .await?;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        custom_relay.on_save(move |endpoint| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let custom_relay = app.global::<CustomRelay>();
+
+            if let Err(e) = enforce::check(Field::Location) {
+                return custom_relay.set_error(e.to_string().into());
+            }
+            let edit = match parse_endpoint(&endpoint) {
+                Ok(edit) => edit,
+                Err(error) => return custom_relay.set_error(error.into()),
+            };
+            custom_relay.set_error("".into());
+
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let relay_settings = rpc.get_settings().await?.relay_settings;
+                let RelaySettings::CustomTunnelEndpoint(mut endpoint) = relay_settings else {
+                    bail!("No custom server to edit");
+                };
+                apply_edit(&mut endpoint, edit);
+                rpc.set_relay_settings(RelaySettings::CustomTunnelEndpoint(endpoint))
+                    .await?;
+                Ok(())
+            });
+        });
+    }
+}
+
+/// Sync the custom server view with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let custom_relay = app.global::<CustomRelay>();
+
+    let RelaySettings::CustomTunnelEndpoint(endpoint) = &settings.relay_settings else {
+        custom_relay.set_active(false);
+        custom_relay.set_endpoint(CustomEndpoint::default());
+        return;
+    };
+
+    let ConnectionConfig::Wireguard(config) = &endpoint.config;
+    let addresses = config
+        .tunnel
+        .addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    custom_relay.set_active(true);
+    custom_relay.set_endpoint(CustomEndpoint {
+        host: endpoint.host.as_str().into(),
+        port: config.peer.endpoint.port().to_string().into(),
+        peer_public_key: config.peer.public_key.to_base64().into(),
+        addresses: addresses.into(),
+    });
+}
+
+fn apply_edit(endpoint: &mut CustomTunnelEndpoint, edit: Edit) {
+    let ConnectionConfig::Wireguard(config) = &mut endpoint.config;
+
+    // The daemon resolves the host when connecting, but keep the endpoint in sync if we can.
+    if let Ok(ip) = edit.host.parse::<IpAddr>() {
+        config.peer.endpoint.set_ip(ip);
+    }
+    config.peer.endpoint.set_port(edit.port);
+    config.peer.public_key = edit.peer_public_key;
+    config.tunnel.addresses = edit.addresses;
+    endpoint.host = edit.host;
+}
+
+/// Validate a custom server entered by the user.
+fn parse_endpoint(endpoint: &CustomEndpoint) -> Result<Edit, &'static str> {
+    let host = endpoint.host.trim();
+    if host.is_empty() {
+        return Err("Please enter a host.");
+    }
+
+    let port = endpoint
+        .port
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&port| port != 0)
+        .ok_or("The port must be a number between 1 and 65535.")?;
+
+    let peer_public_key = PublicKey::from_base64(endpoint.peer_public_key.trim())
+        .map_err(|_| "The public key must be a base64 encoded WireGuard key.")?;
+
+    let addresses = endpoint
+        .addresses
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<IpAddr>, _>>()
+        .map_err(|_| "The addresses must be comma separated IPv4 or IPv6 addresses.")?;
+    if addresses.is_empty() {
+        return Err("Please enter at least one tunnel address.");
+    }
+
+    Ok(Edit {
+        host: host.to_owned(),
+        port,
+        peer_public_key,
+        addresses,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "T9rKPaqQLuIYCXgJcS5Wg9cN/xUmNZjGM0GTbv8gJ3Y=";
+
+    fn endpoint() -> CustomEndpoint {
+        CustomEndpoint {
+            host: " vpn.example.com ".into(),
+            port: "51820".into(),
+            peer_public_key: KEY.into(),
+            addresses: "10.64.0.2, fc00:bbbb:bbbb:bb01::2".into(),
+        }
+    }
+
+    #[test]
+    fn parse_valid_endpoint() {
+        let Ok(edit) = parse_endpoint(&endpoint()) else {
+            panic!("Expected a valid endpoint");
+        };
+        assert_eq!(edit.host, "vpn.example.com");
+        assert_eq!(edit.port, 51820);
+        assert_eq!(edit.peer_public_key.to_base64(), KEY);
+        assert_eq!(
+            edit.addresses,
+            [
+                IpAddr::from([10, 64, 0, 2]),
+                "fc00:bbbb:bbbb:bb01::2".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_empty_addresses() {
+        let endpoint = CustomEndpoint {
+            addresses: "10.64.0.2,,".into(),
+            ..endpoint()
+        };
+        let Ok(edit) = parse_endpoint(&endpoint) else {
+            panic!("Expected a valid endpoint");
+        };
+        assert_eq!(edit.addresses, [IpAddr::from([10, 64, 0, 2])]);
+    }
+
+    #[test]
+    fn reject_invalid_fields() {
+        let error = |endpoint| parse_endpoint(&endpoint).err();
+
+        assert_eq!(
+            error(CustomEndpoint {
+                host: " ".into(),
+                ..endpoint()
+            }),
+            Some("Please enter a host.")
+        );
+        for port in ["", "0", "65536", "wg"] {
+            assert_eq!(
+                error(CustomEndpoint {
+                    port: port.into(),
+                    ..endpoint()
+                }),
+                Some("The port must be a number between 1 and 65535.")
+            );
+        }
+        for key in ["", "not a key", "AAAA"] {
+            assert_eq!(
+                error(CustomEndpoint {
+                    peer_public_key: key.into(),
+                    ..endpoint()
+                }),
+                Some("The public key must be a base64 encoded WireGuard key.")
+            );
+        }
+        assert_eq!(
+            error(CustomEndpoint {
+                addresses: "10.64.0.2, 10.64.0".into(),
+                ..endpoint()
+            }),
+            Some("The addresses must be comma separated IPv4 or IPv6 addresses.")
+        );
+        assert_eq!(
+            error(CustomEndpoint {
+                addresses: " , ".into(),
+                ..endpoint()
+            }),
+            Some("Please enter at least one tunnel address.")
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! DNS settings: custom DNS servers and content blockers.
+
+use std::net::IpAddr;
+
+use anyhow::Context;
+use mullvad_types::settings::{DnsOptions, DnsState, Settings};
+use slint::{
+    ComponentHandle as _, Model as _, ModelRc, SharedString, ToSharedString as _, VecModel,
+};
+
+use crate::{
+    rpc::Rpc,
+    slint_ty::{AppWindow, Dns, DnsBlocker, DnsBlockers, DnsServer},
+};
+
+/// Install callbacks for the DNS settings views.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let dns = app.global::<Dns>();
+
+    {
+        let rpc = rpc.clone();
+        dns.on_set_custom_enabled(move |enabled| {
+            update_dns_options(&rpc, move |options| {
+                options.state = if enabled {
+                    DnsState::Custom
+                } else {
+                    DnsState::Default
+                };
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        dns.on_add_server(move |address| {
+            let Some(address) = app_weak
+                .upgrade()
+                .and_then(|app| parse_server(&app, &address, None))
+            else {
+                return;
+            };
+            update_dns_options(&rpc, move |options| {
+                options.custom_options.addresses.push(address);
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        dns.on_edit_server(move |index, address| {
+            let Some(address) = app_weak
+                .upgrade()
+                .and_then(|app| parse_server(&app, &address, Some(index)))
+            else {
+                return;
+            };
+            update_dns_options(&rpc, move |options| {
+                let server = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| options.custom_options.addresses.get_mut(index))
+                    .context("No DNS server at index")?;
+                *server = address;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        dns.on_remove_server(move |index| {
+            update_dns_options(&rpc, move |options| {
+                let addresses = &mut options.custom_options.addresses;
+                let index = usize::try_from(index)
+                    .ok()
+                    .filter(|&index| index < addresses.len())
+                    .context("No DNS server at index")?;
+                addresses.remove(index);
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        dns.on_move_server(move |from, to| {
+            update_dns_options(&rpc, move |options| {
+                let addresses = &mut options.custom_options.addresses;
+                let from = usize::try_from(from)
+                    .ok()
+                    .filter(|&from| from < addresses.len())
+                    .context("No DNS server at index")?;
+                let address = addresses.remove(from);
+                let to = usize::try_from(to).unwrap_or(0).min(addresses.len());
+                addresses.insert(to, address);
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        dns.on_set_blocker(move |blocker, enabled| {
+            update_dns_options(&rpc, move |options| {
+                let blockers = &mut options.default_options;
+                let block = match blocker {
+                    DnsBlocker::Ads => &mut blockers.block_ads,
+                    DnsBlocker::Trackers => &mut blockers.block_trackers,
+                    DnsBlocker::Malware => &mut blockers.block_malware,
+                    DnsBlocker::Gambling => &mut blockers.block_gambling,
+                    DnsBlocker::AdultContent => &mut blockers.block_adult_content,
+                    DnsBlocker::SocialMedia => &mut blockers.block_social_media,
+                };
+                *block = enabled;
+
+                // Content blockers and custom DNS are mutually exclusive
+                if enabled {
+                    options.state = DnsState::Default;
+                }
+                Ok(())
+            });
+        });
+    }
+}
+
+/// Sync the DNS views with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let dns = app.global::<Dns>();
+    let options = &settings.tunnel_options.dns_options;
+
+    let custom_enabled = matches!(options.state, DnsState::Custom);
+    dns.set_custom_enabled(custom_enabled);
+
+    // The content blockers are only in effect when custom DNS is disabled.
+    let blockers = &options.default_options;
+    dns.set_blockers(if custom_enabled {
+        DnsBlockers::default()
+    } else {
+        DnsBlockers {
+            ads: blockers.block_ads,
+            trackers: blockers.block_trackers,
+            malware: blockers.block_malware,
+            gambling: blockers.block_gambling,
+            adult_content: blockers.block_adult_content,
+            social_media: blockers.block_social_media,
+        }
+    });
+
+    let servers: VecModel<_> = options
+        .custom_options
+        .addresses
+        .iter()
+        .map(|address| DnsServer {
+            address: address.to_shared_string(),
+            needs_lan: is_local(address) && !settings.allow_lan,
+        })
+        .collect();
+    dns.set_servers(ModelRc::new(servers));
+}
+
+/// Fetch the current DNS options, apply `f`, and send them back to the daemon.
+fn update_dns_options<F>(rpc: &Rpc, f: F)
+where
+    F: FnOnce(&mut DnsOptions) -> anyhow::Result<()> + Send + 'static,
+{
+    rpc.spawn_with_rpc(async move |mut rpc| {
+        let mut options = rpc.get_settings().await?.tunnel_options.dns_options;
+        f(&mut options)?;
+        rpc.set_dns_options(options).await?;
+        Ok(())
+    });
+}
+
+/// Validate a DNS server entered by the user. Explains what's wrong in the UI if it's invalid.
+///
+/// `replacing` is the index of the server being edited, if any.
+fn parse_server(app: &AppWindow, address: &str, replacing: Option<i32>) -> Option<IpAddr> {
+    let dns = app.global::<Dns>();
+    let others: Vec<SharedString> = dns
+        .get_servers()
+        .iter()
+        .zip(0..)
+        .filter(|&(_, index)| Some(index) != replacing)
+        .map(|(server, _)| server.address)
+        .collect();
+
+    match validate_server(address, &others) {
+        Ok(address) => {
+            dns.set_error("".into());
+            Some(address)
+        }
+        Err(error) => {
+            dns.set_error(error.into());
+            None
+        }
+    }
+}
+
+/// Parse a DNS server address, which must not be one of the `others` already added.
+fn validate_server(address: &str, others: &[SharedString]) -> Result<IpAddr, &'static str> {
+    match address.trim().parse::<IpAddr>() {
+        Err(_) => Err("Not a valid IPv4 or IPv6 address."),
+        Ok(address) if address.is_unspecified() || address.is_multicast() => {
+            Err("This address can't be used as a DNS server.")
+        }
+        Ok(address) if others.contains(&address.to_shared_string()) => {
+            Err("This server has already been added.")
+        }
+        Ok(address) => Ok(address),
+    }
+}
+
+/// Whether `address` is on a local network, and thus only reachable with LAN sharing enabled.
+fn is_local(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => address.is_private() || address.is_link_local(),
+        IpAddr::V6(address) => address.is_unique_local() || address.is_unicast_link_local(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addresses() {
+        assert_eq!(
+            validate_server(" 1.1.1.1 ", &[]),
+            Ok(IpAddr::from([1, 1, 1, 1]))
+        );
+        assert_eq!(
+            validate_server("2606:4700:4700::1111", &[]),
+            Ok("2606:4700:4700::1111".parse().unwrap())
+        );
+        assert!(validate_server("one.one.one.one", &[]).is_err());
+        assert!(validate_server("1.1.1", &[]).is_err());
+        assert!(validate_server("", &[]).is_err());
+    }
+
+    #[test]
+    fn reject_unusable_addresses() {
+        assert!(validate_server("0.0.0.0", &[]).is_err());
+        assert!(validate_server("::", &[]).is_err());
+        assert!(validate_server("224.0.0.1", &[]).is_err());
+        assert!(validate_server("ff02::1", &[]).is_err());
+    }
+
+    #[test]
+    fn reject_duplicates() {
+        let others = ["1.1.1.1".into(), "fd00::1".into()];
+        assert_eq!(
+            validate_server("1.1.1.1", &others),
+            Err("This server has already been added.")
+        );
+        assert!(validate_server("fd00:0::1", &others).is_err());
+        assert!(validate_server("8.8.8.8", &others).is_ok());
+    }
+
+    #[test]
+    fn local_addresses() {
+        let local = |address: &str| is_local(&address.parse().unwrap());
+        assert!(local("192.168.1.1"));
+        assert!(local("10.0.0.1"));
+        assert!(local("172.16.0.1"));
+        assert!(local("169.254.0.1"));
+        assert!(local("fd00::1"));
+        assert!(local("fe80::1"));
+        assert!(!local("1.1.1.1"));
+        assert!(!local("2606:4700:4700::1111"));
+    }
+}
@@ -0,0 +1,569 @@
+//! Enforce a declarative desired state for some settings, e.g. on managed machines.
+//!
+//! The desired state is read from a TOML file on start. It's applied then, and again whenever
+//! the daemon settings drift from it. In a dry run, the differences are only reported. Settings
+//! that the admin policy forces to a value are enforced the same way. The app refuses to change
+//! enforced settings itself, see [`check`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context as _;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::{
+    constraints::Constraint,
+    relay_constraints::{
+        GeographicLocationConstraint, LocationConstraint, RelayConstraints, RelaySettings,
+        SelectedObfuscation,
+    },
+    settings::Settings,
+};
+use serde::Deserialize;
+use slint::{ComponentHandle as _, ModelRc, SharedString, VecModel};
+
+use crate::{
+    RT, policy,
+    profiles::{self, Field, Profile},
+    rpc::Rpc,
+    slint_ty::{AppWindow, Enforced},
+};
+
+/// System-wide desired state. It takes precedence over the user's.
+const SYSTEM_PATH: Option<&str> = if cfg!(unix) {
+    Some("/etc/mullvad-slint/enforce.toml")
+} else {
+    None
+};
+
+static ENFORCERS: Mutex<Vec<Enforcer>> = Mutex::new(vec![]);
+
+struct Enforcer {
+    source: PathBuf,
+    desired: Profile,
+    dry_run: bool,
+    rpc: Rpc,
+    /// The differences that were reported last. Only new differences are logged and corrected,
+    /// so that the settings events caused by a correction don't start another one. Differences
+    /// that fail to be corrected are removed again, so that they're retried.
+    reported: Vec<String>,
+}
+
+/// The desired-state file, e.g.
+///
+/// ```toml
+/// lockdown_mode = true
+/// obfuscation = "udp2tcp"
+/// location = { country = "se", city = "got" }
+/// ```
+///
+/// Settings that are left out are not enforced.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesiredState {
+    /// Only report differences, don't correct them.
+    #[serde(default)]
+    pub dry_run: bool,
+    pub allow_lan: Option<bool>,
+    pub lockdown_mode: Option<bool>,
+    pub auto_connect: Option<bool>,
+    pub enable_ipv6: Option<bool>,
+    pub daita: Option<bool>,
+    pub obfuscation: Option<Obfuscation>,
+    pub location: Option<Location>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Obfuscation {
+    Auto,
+    Off,
+    Udp2tcp,
+    Shadowsocks,
+    Quic,
+}
+
+/// A relay location. An empty table means any location.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Location {
+    country: Option<String>,
+    city: Option<String>,
+    hostname: Option<String>,
+}
+
+impl DesiredState {
+    pub fn into_profile(self) -> anyhow::Result<Profile> {
+        let obfuscation = self.obfuscation.map(|obfuscation| match obfuscation {
+            Obfuscation::Auto => SelectedObfuscation::Auto,
+            Obfuscation::Off => SelectedObfuscation::Off,
+            Obfuscation::Udp2tcp => SelectedObfuscation::Udp2Tcp,
+            Obfuscation::Shadowsocks => SelectedObfuscation::Shadowsocks,
+            Obfuscation::Quic => SelectedObfuscation::Quic,
+        });
+        let location = self.location.map(Location::into_constraint).transpose()?;
+
+        Ok(Profile {
+            name: String::new(),
+            allow_lan: self.allow_lan,
+            lockdown_mode: self.lockdown_mode,
+            auto_connect: self.auto_connect,
+            enable_ipv6: self.enable_ipv6,
+            daita: self.daita,
+            obfuscation,
+            location,
+        })
+    }
+}
+
+impl Location {
+    fn into_constraint(self) -> anyhow::Result<Constraint<LocationConstraint>> {
+        let location = match (self.country, self.city, self.hostname) {
+            (None, None, None) => return Ok(Constraint::Any),
+            (Some(country), None, None) => GeographicLocationConstraint::Country(country),
+            (Some(country), Some(city), None) => GeographicLocationConstraint::City(country, city),
+            (Some(country), Some(city), Some(hostname)) => {
+                GeographicLocationConstraint::Hostname(country, city, hostname)
+            }
+            _ => anyhow::bail!("A location city needs a country, and a hostname needs a city"),
+        };
+        Ok(Constraint::Only(LocationConstraint::Location(location)))
+    }
+}
+
+/// Read the desired-state file, if there is one, and start enforcing it.
+///
+/// `path` overrides the default locations: the system-wide file, or else
+/// `<config_home>/mullvad-slint/enforce.toml`. `dry_run` forces a dry run regardless of the file.
+///
+/// Settings that the admin policy restricts are left to the policy, so this must be called after
+/// [`policy::setup`].
+pub fn setup(
+    app: &AppWindow,
+    rpc: &Rpc,
+    path: Option<PathBuf>,
+    config_home: Option<&Path>,
+    dry_run: bool,
+) {
+    let Some(source) = path.or_else(|| find(SYSTEM_PATH.map(Path::new), config_home)) else {
+        return;
+    };
+
+    let desired = fs::read_to_string(&source)
+        .with_context(|| format!("Failed to read {}", source.display()))
+        .and_then(|desired| {
+            toml::from_str::<DesiredState>(&desired)
+                .with_context(|| format!("Failed to parse {}", source.display()))
+        });
+    let desired = match desired {
+        Ok(desired) => desired,
+        Err(e) => {
+            tracing::error!("Not enforcing any settings: {e:#}");
+            return;
+        }
+    };
+
+    let dry_run = dry_run || desired.dry_run;
+    let desired = match desired.into_profile() {
+        Ok(desired) => desired,
+        Err(e) => {
+            tracing::error!("Not enforcing any settings: {}: {e:#}", source.display());
+            return;
+        }
+    };
+
+    let (restricted, unrestricted): (Vec<Field>, Vec<Field>) = desired
+        .fields()
+        .into_iter()
+        .partition(|&field| policy::restriction(field).is_some());
+    for field in restricted {
+        tracing::warn!(
+            "Not enforcing {} from {}, since it's managed by the admin policy",
+            field.label(),
+            source.display()
+        );
+    }
+    let desired = desired.subset(&unrestricted);
+
+    let fields: Vec<&str> = desired.fields().iter().map(|field| field.label()).collect();
+    tracing::info!(
+        "Enforcing {} from {}{}",
+        fields.join(", "),
+        source.display(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+
+    let enforced = app.global::<Enforced>();
+    enforced.set_source(source.display().to_string().into());
+    enforced.set_dry_run(dry_run);
+    if !dry_run {
+        let locked = |field: Field| field.is_set(&desired);
+        enforced.set_allow_lan(locked(Field::AllowLan));
+        enforced.set_lockdown_mode(locked(Field::LockdownMode));
+        enforced.set_auto_connect(locked(Field::AutoConnect));
+        enforced.set_enable_ipv6(locked(Field::EnableIpv6));
+        enforced.set_daita(locked(Field::Daita));
+        enforced.set_obfuscation(locked(Field::Obfuscation));
+        enforced.set_location(locked(Field::Location));
+    }
+
+    ENFORCERS.lock().unwrap().push(Enforcer {
+        source,
+        desired,
+        dry_run,
+        rpc: rpc.clone(),
+        reported: vec![],
+    });
+}
+
+/// Enforce `desired`, which comes from `source`, without locking it in the UI.
+pub fn force(rpc: &Rpc, source: PathBuf, desired: Profile) {
+    ENFORCERS.lock().unwrap().push(Enforcer {
+        source,
+        desired,
+        dry_run: false,
+        rpc: rpc.clone(),
+        reported: vec![],
+    });
+}
+
+/// Fail if `field` may not be changed, because the admin policy restricts it or it's enforced.
+///
+/// A change to an enforced setting would only be changed back, so it's refused up front.
+pub fn check(field: Field) -> anyhow::Result<()> {
+    policy::check(field)?;
+    let enforcers = ENFORCERS.lock().unwrap();
+    match enforcers.iter().find(|enforcer| enforcer.locks(field)) {
+        None => Ok(()),
+        Some(enforcer) => anyhow::bail!(
+            "{} is enforced by {}",
+            field.label(),
+            enforcer.source.display()
+        ),
+    }
+}
+
+/// The desired-state file to use: `system` if it exists, or else the user's.
+fn find(system: Option<&Path>, config_home: Option<&Path>) -> Option<PathBuf> {
+    if let Some(system) = system
+        && system.exists()
+    {
+        return Some(system.to_path_buf());
+    }
+    let path = config_home?.join("mullvad-slint").join("enforce.toml");
+    path.exists().then_some(path)
+}
+
+/// Compare the daemon settings with the desired states, and correct any drift.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let differences: Vec<SharedString> = ENFORCERS
+        .lock()
+        .unwrap()
+        .iter_mut()
+        .enumerate()
+        .flat_map(|(index, enforcer)| enforcer.update_settings(index, settings))
+        .map(SharedString::from)
+        .collect();
+    app.global::<Enforced>()
+        .set_differences(ModelRc::new(VecModel::from(differences)));
+}
+
+impl Enforcer {
+    fn locks(&self, field: Field) -> bool {
+        !self.dry_run && field.is_set(&self.desired)
+    }
+
+    /// Correct any drift from the desired state. In a dry run, how the settings differ from it is
+    /// returned instead.
+    ///
+    /// `index` is the position of this enforcer in [`ENFORCERS`].
+    fn update_settings(&mut self, index: usize, settings: &Settings) -> Vec<String> {
+        let (differences, new_drift, new_differences) = self.track(settings);
+        for difference in &new_differences {
+            if self.dry_run {
+                tracing::info!("Dry run, not correcting {difference}");
+            } else {
+                tracing::info!("Correcting {difference}");
+            }
+        }
+        if self.dry_run {
+            return differences;
+        }
+        if new_drift.is_empty() {
+            return vec![];
+        }
+
+        // Only touch the settings that have drifted.
+        let correction = self.desired.subset(&new_drift);
+        let source = self.source.clone();
+        let rpc = self.rpc.clone();
+        RT.spawn(async move {
+            let result = rpc
+                .with_rpc(async move |mut rpc| {
+                    correct(&mut rpc, &correction).await?;
+                    let settings = rpc.get_settings().await?;
+                    Ok(correction.drift(&settings))
+                })
+                .await;
+            match result {
+                Ok(remaining) if remaining.is_empty() => {}
+                Ok(remaining) => {
+                    let labels: Vec<&str> = remaining.iter().map(|field| field.label()).collect();
+                    tracing::warn!(
+                        "{} changed again while enforcing {}",
+                        labels.join(", "),
+                        source.display()
+                    );
+                }
+                Err(e) => {
+                    tracing::error!("Failed to enforce {}: {e:#}", source.display());
+                    // Not corrected, so try again on the next settings event.
+                    ENFORCERS.lock().unwrap()[index].forget(&new_differences);
+                }
+            }
+        });
+        vec![]
+    }
+
+    /// Compare `settings` with the desired state, and remember the differences as reported.
+    ///
+    /// Returns all differences, and the drifted fields and differences that weren't reported
+    /// before.
+    fn track(&mut self, settings: &Settings) -> (Vec<String>, Vec<Field>, Vec<String>) {
+        let drift = self.desired.drift(settings);
+        let differences: Vec<String> = drift
+            .iter()
+            .map(|field| field.describe_drift(&self.desired, settings))
+            .collect();
+
+        let (new_drift, new_differences): (Vec<Field>, Vec<String>) = drift
+            .into_iter()
+            .zip(&differences)
+            .filter(|(_, difference)| !self.reported.contains(*difference))
+            .map(|(field, difference)| (field, difference.clone()))
+            .unzip();
+        self.reported = differences.clone();
+        (differences, new_drift, new_differences)
+    }
+
+    /// Forget that `differences` were reported, so that they're corrected again.
+    fn forget(&mut self, differences: &[String]) {
+        self.reported
+            .retain(|difference| !differences.contains(difference));
+    }
+}
+
+/// Apply `correction`.
+async fn correct(rpc: &mut MullvadProxyClient, correction: &Profile) -> anyhow::Result<()> {
+    // A custom server has no location, so switch back to the Mullvad relays first.
+    if correction.location.is_some()
+        && let RelaySettings::CustomTunnelEndpoint(_) = rpc.get_settings().await?.relay_settings
+    {
+        rpc.set_relay_settings(RelaySettings::Normal(RelayConstraints::default()))
+            .await?;
+    }
+    profiles::apply(rpc, correction).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enforcer(desired: Profile, dry_run: bool) -> Enforcer {
+        Enforcer {
+            source: PathBuf::from("enforce.toml"),
+            desired,
+            dry_run,
+            rpc: Rpc::new(),
+            reported: vec![],
+        }
+    }
+
+    fn location(country: Option<&str>, city: Option<&str>, hostname: Option<&str>) -> Location {
+        Location {
+            country: country.map(str::to_owned),
+            city: city.map(str::to_owned),
+            hostname: hostname.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn parse_desired_state() {
+        let desired: DesiredState = toml::from_str(
+            r#"
+            dry_run = true
+            lockdown_mode = true
+            obfuscation = "udp2tcp"
+            location = { country = "se", city = "got" }
+            "#,
+        )
+        .unwrap();
+        assert!(desired.dry_run);
+
+        let profile = desired.into_profile().unwrap();
+        assert_eq!(profile.lockdown_mode, Some(true));
+        assert_eq!(profile.obfuscation, Some(SelectedObfuscation::Udp2Tcp));
+        assert_eq!(
+            profile.fields(),
+            [Field::LockdownMode, Field::Obfuscation, Field::Location]
+        );
+    }
+
+    #[test]
+    fn reject_unknown_settings() {
+        assert!(toml::from_str::<DesiredState>("lockdown = true").is_err());
+        assert!(toml::from_str::<DesiredState>(r#"obfuscation = "tor""#).is_err());
+        assert!(toml::from_str::<DesiredState>("location = { region = \"eu\" }").is_err());
+    }
+
+    #[test]
+    fn not_a_dry_run_by_default() {
+        let desired: DesiredState = toml::from_str("allow_lan = false").unwrap();
+        assert!(!desired.dry_run);
+    }
+
+    #[test]
+    fn location_constraints() {
+        let only = |location| Constraint::Only(LocationConstraint::Location(location));
+
+        assert_eq!(
+            location(None, None, None).into_constraint().unwrap(),
+            Constraint::Any
+        );
+        assert_eq!(
+            location(Some("se"), None, None).into_constraint().unwrap(),
+            only(GeographicLocationConstraint::Country("se".to_owned()))
+        );
+        assert_eq!(
+            location(Some("se"), Some("got"), None)
+                .into_constraint()
+                .unwrap(),
+            only(GeographicLocationConstraint::City(
+                "se".to_owned(),
+                "got".to_owned()
+            ))
+        );
+        assert_eq!(
+            location(Some("se"), Some("got"), Some("se-got-wg-001"))
+                .into_constraint()
+                .unwrap(),
+            only(GeographicLocationConstraint::Hostname(
+                "se".to_owned(),
+                "got".to_owned(),
+                "se-got-wg-001".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn reject_incomplete_locations() {
+        assert!(location(None, Some("got"), None).into_constraint().is_err());
+        assert!(
+            location(Some("se"), None, Some("se-got-wg-001"))
+                .into_constraint()
+                .is_err()
+        );
+        assert!(
+            location(None, None, Some("se-got-wg-001"))
+                .into_constraint()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn system_file_takes_precedence() {
+        let system = tempfile::tempdir().unwrap();
+        let system_path = system.path().join("enforce.toml");
+        let config_home = tempfile::tempdir().unwrap();
+        let user_path = config_home
+            .path()
+            .join("mullvad-slint")
+            .join("enforce.toml");
+
+        assert_eq!(find(Some(&system_path), Some(config_home.path())), None);
+
+        fs::create_dir_all(user_path.parent().unwrap()).unwrap();
+        fs::write(&user_path, "").unwrap();
+        assert_eq!(
+            find(Some(&system_path), Some(config_home.path())),
+            Some(user_path.clone())
+        );
+        assert_eq!(find(None, Some(config_home.path())), Some(user_path));
+
+        fs::write(&system_path, "").unwrap();
+        assert_eq!(
+            find(Some(&system_path), Some(config_home.path())),
+            Some(system_path.clone())
+        );
+        assert_eq!(find(Some(&system_path), None), Some(system_path));
+    }
+
+    #[test]
+    fn only_track_new_differences() {
+        let desired = Profile {
+            allow_lan: Some(true),
+            ..Profile::default()
+        };
+        let mut enforcer = enforcer(desired, false);
+        let mut settings = Settings::default();
+        settings.allow_lan = false;
+
+        let (differences, new_drift, new_differences) = enforcer.track(&settings);
+        assert_eq!(new_drift, [Field::AllowLan]);
+        assert_eq!(new_differences, differences);
+        assert_eq!(enforcer.reported, differences);
+
+        // Already reported, e.g. while the correction is in flight.
+        let (again, new_drift, new_differences) = enforcer.track(&settings);
+        assert_eq!(again, differences);
+        assert!(new_drift.is_empty());
+        assert!(new_differences.is_empty());
+
+        // A failed correction is retried.
+        enforcer.forget(&differences);
+        let (_, new_drift, _) = enforcer.track(&settings);
+        assert_eq!(new_drift, [Field::AllowLan]);
+
+        // Corrected, so drifting again is new.
+        settings.allow_lan = true;
+        let (differences, new_drift, _) = enforcer.track(&settings);
+        assert!(differences.is_empty());
+        assert!(new_drift.is_empty());
+        assert!(enforcer.reported.is_empty());
+        settings.allow_lan = false;
+        let (_, new_drift, _) = enforcer.track(&settings);
+        assert_eq!(new_drift, [Field::AllowLan]);
+    }
+
+    #[test]
+    fn dry_run_returns_all_differences() {
+        let desired = Profile {
+            lockdown_mode: Some(true),
+            ..Profile::default()
+        };
+        let mut enforcer = enforcer(desired, true);
+        let mut settings = Settings::default();
+        settings.lockdown_mode = false;
+
+        let differences = enforcer.update_settings(0, &settings);
+        assert_eq!(differences, ["Lockdown mode: false → true"]);
+        // Still returned once reported, so that the UI keeps listing it.
+        assert_eq!(enforcer.update_settings(0, &settings), differences);
+    }
+
+    #[test]
+    fn only_lock_enforced_settings() {
+        let desired = Profile {
+            daita: Some(true),
+            ..Profile::default()
+        };
+        let enforcer = self::enforcer(desired.clone(), false);
+        assert!(enforcer.locks(Field::Daita));
+        assert!(!enforcer.locks(Field::AllowLan));
+
+        let dry_run = self::enforcer(desired, true);
+        assert!(!dry_run.locks(Field::Daita));
+    }
+}
@@ -2,9 +2,27 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 pub mod api;
+mod api_access;
+mod app_info;
+#[cfg(target_os = "linux")]
+mod autostart;
+mod compat;
+mod custom_relay;
+mod dns;
+mod enforce;
 #[cfg(feature = "map")]
 mod map;
+mod obfuscation;
+mod policy;
+mod ports;
+mod preferences;
+mod problem_report;
+mod profiles;
+mod relay_overrides;
 mod rpc;
+mod settings_transfer;
+mod stats;
+mod wireguard;
 
 #[cfg(target_os = "linux")]
 mod split_tunneling;
@@ -14,9 +32,13 @@ mod tray;
 
 mod slint_ty;
 
-use std::{rc::Rc, sync::LazyLock};
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::LazyLock,
+};
 
-use anyhow::{Context, bail};
+use anyhow::Context;
 use clap::Parser;
 use futures::StreamExt as _;
 use mullvad_management_interface::client::DaemonEvent;
@@ -32,8 +54,10 @@ use slint::{ComponentHandle as _, Model, ModelRc, ToSharedString, VecModel};
 use slint_ty::Country;
 
 use crate::{
+    compat::DaemonFeature,
+    profiles::Field,
     rpc::Rpc,
-    slint_ty::{ConnectionState, Route, View},
+    slint_ty::{ConnectionDetails, ConnectionState, ErrorInfo, Route, View},
 };
 
 /// Convert gRPC relay list from Rust to a Slint list of countries.
@@ -51,6 +75,8 @@ fn relay_list_to_slint(relay_list: &RelayList) -> ModelRc<Country> {
                         .iter()
                         .map(|relay| slint_ty::Relay {
                             hostname: relay.hostname.to_shared_string(),
+                            has_override: false,
+                            favourite: false,
                         })
                         .collect::<VecModel<_>>();
                     slint_ty::City {
@@ -59,6 +85,7 @@ fn relay_list_to_slint(relay_list: &RelayList) -> ModelRc<Country> {
                         relays: ModelRc::from(Rc::new(relays)),
                         latitude: city.latitude as f32,
                         longitude: city.longitude as f32,
+                        favourite: false,
                     }
                 })
                 .collect::<VecModel<_>>();
@@ -67,6 +94,7 @@ fn relay_list_to_slint(relay_list: &RelayList) -> ModelRc<Country> {
                 name: country.name.to_shared_string(),
                 code: country.code.to_shared_string(),
                 cities: ModelRc::from(Rc::new(cities)),
+                favourite: false,
             }
         })
         .collect::<VecModel<_>>();
@@ -85,6 +113,23 @@ static RT: LazyLock<tokio::runtime::Runtime> = LazyLock::new(|| {
 struct Opt {
     #[clap(long, env = "RUST_LOG", default_value = "info")]
     log_filter: String,
+
+    /// Where sysfs is mounted. Tunnel statistics are read from here.
+    #[clap(long, env = "MULLVAD_SLINT_SYSFS_ROOT", default_value = "/sys")]
+    sysfs_root: PathBuf,
+
+    /// Override the user config directory, e.g. `~/.config`.
+    #[clap(long)]
+    config_home: Option<PathBuf>,
+
+    /// Desired-state file to enforce, instead of `/etc/mullvad-slint/enforce.toml` or
+    /// `<config home>/mullvad-slint/enforce.toml`.
+    #[clap(long)]
+    enforce: Option<PathBuf>,
+
+    /// Only log how the settings differ from the desired state, without correcting them.
+    #[clap(long)]
+    enforce_dry_run: bool,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -92,6 +137,7 @@ fn main() -> anyhow::Result<()> {
 
     let fmt_subscriber = tracing_subscriber::FmtSubscriber::builder()
         .with_env_filter(&opt.log_filter)
+        .with_writer(problem_report::log_writer)
         .finish();
     tracing::subscriber::set_global_default(fmt_subscriber)
         .context("Failed to initialize tracing subscriber")?;
@@ -109,42 +155,45 @@ fn main() -> anyhow::Result<()> {
     let rpc = Rpc::new();
 
     #[cfg(all(target_os = "linux", feature = "tray-icon"))]
-    let _tray = tray::create_tray_icon();
+    if let Err(e) = tray::create_tray_icon() {
+        tracing::warn!("Failed to create tray icon: {e:#}");
+    }
 
     let app = slint_ty::AppWindow::new()?;
 
     let ui_state = app.global::<slint_ty::State>();
 
-    {
-        // Install connect button callback
-        let rpc = rpc.clone();
-        ui_state.on_connect_button(move || {
-            rpc.spawn_with_rpc(async move |mut rpc| {
-                if rpc.get_tunnel_state().await?.is_disconnected() {
-                    rpc.connect_tunnel().await?;
-                } else {
-                    rpc.disconnect_tunnel().await?;
-                }
-                Ok(())
+    macro_rules! bind_rpc {
+        ($ui_callback:ident, $rpc_fn:ident) => {{
+            let rpc = rpc.clone();
+            ui_state.$ui_callback(move || {
+                rpc.spawn_with_rpc(async move |mut rpc| {
+                    rpc.$rpc_fn().await?;
+                    Ok(())
+                });
             });
-        });
+        }};
     }
 
+    // Install connect button callbacks
+    bind_rpc!(on_connect, connect_tunnel);
+    bind_rpc!(on_cancel, disconnect_tunnel);
+    bind_rpc!(on_disconnect, disconnect_tunnel);
+    bind_rpc!(on_reconnect, reconnect_tunnel);
+    bind_rpc!(on_dismiss_error, disconnect_tunnel);
+
     {
         // Install select country callback
         let rpc = rpc.clone();
         ui_state.on_select_country(move |country| {
-            rpc.spawn_with_rpc(async move |mut rpc| {
-                let relay_settings = rpc.get_settings().await?.relay_settings;
-                let RelaySettings::Normal(mut relay_constraints) = relay_settings else {
-                    bail!("Can't configure custom relays");
-                };
+            if let Err(e) = enforce::check(Field::Location) {
+                tracing::warn!("{e}");
+                return;
+            }
+            rpc.update_relay_constraints(move |relay_constraints| {
                 relay_constraints.location = Constraint::Only(LocationConstraint::Location(
                     GeographicLocationConstraint::Country(country.code.into()),
                 ));
-                rpc.set_relay_settings(RelaySettings::Normal(relay_constraints))
-                    .await?;
-
                 Ok(())
             });
         });
@@ -154,16 +203,14 @@ fn main() -> anyhow::Result<()> {
         // Install select city callback
         let rpc = rpc.clone();
         ui_state.on_select_city(move |country, city| {
-            rpc.spawn_with_rpc(async move |mut rpc| {
-                let relay_settings = rpc.get_settings().await?.relay_settings;
-                let RelaySettings::Normal(mut relay_constraints) = relay_settings else {
-                    bail!("Can't configure custom relays");
-                };
+            if let Err(e) = enforce::check(Field::Location) {
+                tracing::warn!("{e}");
+                return;
+            }
+            rpc.update_relay_constraints(move |relay_constraints| {
                 relay_constraints.location = Constraint::Only(LocationConstraint::Location(
                     GeographicLocationConstraint::City(country.code.into(), city.code.into()),
                 ));
-                rpc.set_relay_settings(RelaySettings::Normal(relay_constraints))
-                    .await?;
                 Ok(())
             });
         });
@@ -173,11 +220,11 @@ fn main() -> anyhow::Result<()> {
         // Install select relay callback
         let rpc = rpc.clone();
         ui_state.on_select_relay(move |country, city, relay| {
-            rpc.spawn_with_rpc(async move |mut rpc| {
-                let relay_settings = rpc.get_settings().await?.relay_settings;
-                let RelaySettings::Normal(mut relay_constraints) = relay_settings else {
-                    bail!("Can't configure custom relays");
-                };
+            if let Err(e) = enforce::check(Field::Location) {
+                tracing::warn!("{e}");
+                return;
+            }
+            rpc.update_relay_constraints(move |relay_constraints| {
                 relay_constraints.location = Constraint::Only(LocationConstraint::Location(
                     GeographicLocationConstraint::Hostname(
                         country.code.into(),
@@ -185,8 +232,6 @@ fn main() -> anyhow::Result<()> {
                         relay.hostname.into(),
                     ),
                 ));
-                rpc.set_relay_settings(RelaySettings::Normal(relay_constraints))
-                    .await?;
                 Ok(())
             });
         });
@@ -196,23 +241,34 @@ fn main() -> anyhow::Result<()> {
         // Install device ip version callback
         let rpc = rpc.clone();
         ui_state.on_set_device_ip_version(move |device_ip_version| {
-            rpc.spawn_with_rpc(async move |mut rpc| {
-                let relay_settings = rpc.get_settings().await?.relay_settings;
-                let RelaySettings::Normal(mut relay_constraints) = relay_settings else {
-                    bail!("Can't configure custom relays");
-                };
+            rpc.update_relay_constraints(move |relay_constraints| {
                 relay_constraints.wireguard_constraints.ip_version = device_ip_version.into();
-                rpc.set_relay_settings(RelaySettings::Normal(relay_constraints))
+                Ok(())
+            });
+        });
+    }
+
+    {
+        // Install quantum resistance callback
+        let rpc = rpc.clone();
+        ui_state.on_set_quantum_resistance(move |quantum_resistance| {
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                rpc.set_quantum_resistant_tunnel(quantum_resistance.into())
                     .await?;
                 Ok(())
             });
         });
     }
 
+    // The optional check, e.g. `policy::check(Field::Daita)`, must pass to change the setting.
     macro_rules! bind_boolean_rpc {
-        ($ui_callback:ident, $rpc_fn:ident) => {{
+        ($ui_callback:ident, $rpc_fn:ident $(, $module:ident::check($field:expr))?) => {{
             let rpc = rpc.clone();
             ui_state.$ui_callback(move |enabled| {
+                $(if let Err(e) = $module::check($field) {
+                    tracing::warn!("{e}");
+                    return;
+                })?
                 rpc.spawn_with_rpc(async move |mut rpc| {
                     rpc.$rpc_fn(enabled).await?;
                     Ok(())
@@ -221,10 +277,50 @@ fn main() -> anyhow::Result<()> {
         }};
     }
 
-    bind_boolean_rpc!(on_set_allow_lan, set_allow_lan);
-    bind_boolean_rpc!(on_set_enable_ipv6, set_enable_ipv6);
-    bind_boolean_rpc!(on_set_daita_enabled, set_enable_daita);
-    bind_boolean_rpc!(on_set_daita_direct_only, set_daita_direct_only);
+    bind_boolean_rpc!(
+        on_set_allow_lan,
+        set_allow_lan,
+        enforce::check(Field::AllowLan)
+    );
+    bind_boolean_rpc!(
+        on_set_lockdown_mode,
+        set_lockdown_mode,
+        enforce::check(Field::LockdownMode)
+    );
+    bind_boolean_rpc!(
+        on_set_auto_connect,
+        set_auto_connect,
+        enforce::check(Field::AutoConnect)
+    );
+    bind_boolean_rpc!(
+        on_set_enable_ipv6,
+        set_enable_ipv6,
+        enforce::check(Field::EnableIpv6)
+    );
+    {
+        let rpc = rpc.for_feature(DaemonFeature::Daita);
+        bind_boolean_rpc!(
+            on_set_daita_enabled,
+            set_enable_daita,
+            enforce::check(Field::Daita)
+        );
+        // Only DAITA itself is enforced, but the admin policy covers all of its settings.
+        bind_boolean_rpc!(
+            on_set_daita_direct_only,
+            set_daita_direct_only,
+            policy::check(Field::Daita)
+        );
+    }
+
+    dns::setup(&app, &rpc);
+    obfuscation::setup(&app, &rpc);
+    wireguard::setup(&app, &rpc);
+    api_access::setup(&app, &rpc);
+    custom_relay::setup(&app, &rpc);
+    relay_overrides::setup(&app, &rpc);
+    problem_report::setup(&app, &rpc);
+    app_info::setup(&app, &rpc);
+    settings_transfer::setup(&app, &rpc);
 
     // Populate relay list
     let app_weak = app.as_weak();
@@ -236,6 +332,10 @@ fn main() -> anyhow::Result<()> {
         app_weak.upgrade_in_event_loop(move |app| {
             let countries = relay_list_to_slint(&relay_list);
             app.global::<slint_ty::RelayList>().set_countries(countries);
+            relay_overrides::mark_relays(&app);
+            preferences::mark_favourites(&app);
+            obfuscation::update_relay_list(&app, &relay_list);
+            wireguard::update_relay_list(&app, &relay_list);
         })?;
 
         anyhow::Ok(())
@@ -261,6 +361,7 @@ fn main() -> anyhow::Result<()> {
     async fn listen_for_events(
         mut rpc: mullvad_management_interface::MullvadProxyClient,
         app_weak: slint::Weak<slint_ty::AppWindow>,
+        sysfs_root: PathBuf,
     ) -> anyhow::Result<()> {
         let mut events = rpc
             .events_listen()
@@ -276,9 +377,37 @@ fn main() -> anyhow::Result<()> {
             .context("Failed to query tunnel state")?;
 
         let mut last_latlong = (0.0, 0.0);
+        let mut session: Option<stats::Session> = None;
         let mut update_state = |tunnel_state: &TunnelState| {
+            #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+            tray::set_tunnel_state(tunnel_state);
+
+            // The session starts when we first become connected, and ends when we leave that state.
+            match tunnel_state {
+                TunnelState::Connected { endpoint, .. } => {
+                    if session.is_none() {
+                        let interface = endpoint
+                            .tunnel_interface
+                            .clone()
+                            .unwrap_or_else(|| stats::DEFAULT_INTERFACE.to_owned());
+                        session = Some(stats::Session::start(
+                            sysfs_root.clone(),
+                            interface,
+                            app_weak.clone(),
+                        ));
+                    }
+                }
+                _ => session = None,
+            }
+
             let location = tunnel_state.get_location();
             let conn_state = ConnectionState::from(tunnel_state);
+            let details = ConnectionDetails::from(tunnel_state);
+            let features = slint_ty::active_features(tunnel_state);
+            let error = match tunnel_state {
+                TunnelState::Error(error_state) => ErrorInfo::from(error_state),
+                _ => ErrorInfo::default(),
+            };
 
             let hostname = location
                 .and_then(|l| l.hostname.as_deref())
@@ -312,6 +441,9 @@ fn main() -> anyhow::Result<()> {
                 }
                 let state = app.global::<slint_ty::State>();
                 state.set_conn(conn_state);
+                state.set_details(details);
+                state.set_features(ModelRc::new(VecModel::from(features)));
+                state.set_error(error);
                 state.set_location(location);
                 state.set_relay_hostname(hostname);
             })
@@ -366,8 +498,21 @@ fn main() -> anyhow::Result<()> {
                 let ui_state = app.global::<slint_ty::State>();
 
                 update_relay_settings(&ui_state, &settings.relay_settings);
+                dns::update_settings(&app, &settings);
+                obfuscation::update_settings(&app, &settings);
+                wireguard::update_settings(&app, &settings);
+                api_access::update_settings(&app, &settings);
+                custom_relay::update_settings(&app, &settings);
+                relay_overrides::update_settings(&app, &settings);
+                profiles::update_settings(&app, &settings);
+                enforce::update_settings(&app, &settings);
                 ui_state.set_allow_lan(settings.allow_lan);
+                ui_state.set_lockdown_mode(settings.lockdown_mode);
+                ui_state.set_auto_connect(settings.auto_connect);
                 ui_state.set_enable_ipv6(settings.tunnel_options.generic.enable_ipv6);
+                ui_state.set_quantum_resistance(
+                    settings.tunnel_options.wireguard.quantum_resistant.into(),
+                );
                 ui_state.set_daita_enabled(settings.tunnel_options.wireguard.daita.enabled);
                 ui_state.set_daita_direct_only(
                     !settings
@@ -381,6 +526,15 @@ fn main() -> anyhow::Result<()> {
 
         update_state(&tunnel_state)?;
         update_settings(&settings)?;
+        wireguard::update_key(&app_weak, rpc.get_wireguard_key().await.ok());
+        match rpc.get_current_api_access_method().await {
+            Ok(method) => api_access::update_current(&app_weak, &method),
+            Err(e) => tracing::warn!("Failed to query current API access method: {e}"),
+        }
+        match rpc.get_version_info().await {
+            Ok(version_info) => app_info::update_version_info(&app_weak, &version_info),
+            Err(e) => tracing::warn!("Failed to query app version info: {e}"),
+        }
 
         let _ = app_weak.upgrade_in_event_loop(|app| {
             app.global::<Route>()
@@ -397,6 +551,16 @@ fn main() -> anyhow::Result<()> {
                     settings = new;
                     update_settings(&settings)?;
                 }
+                DaemonEvent::Device(_) => {
+                    // E.g. the key was rotated, or we logged out.
+                    wireguard::update_key(&app_weak, rpc.get_wireguard_key().await.ok());
+                }
+                DaemonEvent::NewAccessMethod(method) => {
+                    api_access::update_current(&app_weak, &method);
+                }
+                DaemonEvent::AppVersionInfo(version_info) => {
+                    app_info::update_version_info(&app_weak, &version_info);
+                }
                 _ => continue,
             }
         }
@@ -405,8 +569,9 @@ fn main() -> anyhow::Result<()> {
     }
 
     let app_weak = app.as_weak();
+    let sysfs_root = opt.sysfs_root.clone();
     rpc.spawn_with_rpc_retry_on_error(async move |rpc| {
-        listen_for_events(rpc, app_weak.clone())
+        listen_for_events(rpc, app_weak.clone(), sysfs_root.clone())
             .await
             .inspect_err(|_| {
                 let _ = app_weak.upgrade_in_event_loop(|app| {
@@ -420,6 +585,28 @@ fn main() -> anyhow::Result<()> {
     #[cfg(target_os = "linux")]
     split_tunneling::setup(&app);
 
+    let config_home = opt.config_home.clone().or_else(dirs::config_dir);
+    policy::setup(&app, &rpc, Path::new(policy::PATH));
+    enforce::setup(
+        &app,
+        &rpc,
+        opt.enforce.clone(),
+        config_home.as_deref(),
+        opt.enforce_dry_run,
+    );
+
+    match &config_home {
+        Some(config_home) => {
+            profiles::setup(&app, &rpc, config_home);
+            preferences::setup(&app, config_home);
+            #[cfg(target_os = "linux")]
+            autostart::setup(&app, autostart::Autostart::from_env(config_home));
+        }
+        None => tracing::warn!(
+            "No config directory, preferences, profiles and launching on start-up are unavailable"
+        ),
+    }
+
     #[cfg(feature = "map")]
     {
         let app_weak = app.as_weak();
@@ -0,0 +1,169 @@
+//! Anti-censorship (obfuscation) settings.
+
+use std::ops::RangeInclusive;
+
+use mullvad_types::{
+    constraints::Constraint,
+    relay_constraints::{ObfuscationSettings, SelectedObfuscation},
+    relay_list::RelayList,
+    settings::Settings,
+};
+use slint::{ComponentHandle as _, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    compat::DaemonFeature,
+    enforce, policy, ports,
+    profiles::Field,
+    rpc::Rpc,
+    slint_ty::{AntiCensorship, AppWindow},
+};
+
+/// Obfuscation modes, in the order they're listed in the UI.
+const MODES: &[(SelectedObfuscation, &str)] = &[
+    (SelectedObfuscation::Auto, "Automatic"),
+    (SelectedObfuscation::Off, "Off"),
+    (SelectedObfuscation::Udp2Tcp, "UDP-over-TCP"),
+    (SelectedObfuscation::Shadowsocks, "Shadowsocks"),
+    (SelectedObfuscation::Quic, "QUIC"),
+];
+
+/// Obfuscation modes with a configurable port.
+#[derive(Clone, Copy)]
+enum PortSetting {
+    Udp2Tcp,
+    Shadowsocks,
+}
+
+impl PortSetting {
+    /// Port ranges that the relays accept for this mode.
+    fn allowed_ports(self, relay_list: &RelayList) -> Vec<RangeInclusive<u16>> {
+        match self {
+            PortSetting::Udp2Tcp => relay_list
+                .wireguard
+                .udp2tcp_ports
+                .iter()
+                .map(|&port| port..=port)
+                .collect(),
+            PortSetting::Shadowsocks => relay_list.wireguard.shadowsocks_port_ranges.clone(),
+        }
+    }
+
+    fn port_mut(self, settings: &mut ObfuscationSettings) -> &mut Constraint<u16> {
+        match self {
+            PortSetting::Udp2Tcp => &mut settings.udp2tcp.port,
+            PortSetting::Shadowsocks => &mut settings.shadowsocks.port,
+        }
+    }
+}
+
+/// Install callbacks for the anti-censorship view.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let rpc = &rpc.for_feature(DaemonFeature::Obfuscation);
+    let anti_censorship = app.global::<AntiCensorship>();
+    anti_censorship.set_modes(mode_names(None));
+
+    {
+        let rpc = rpc.clone();
+        anti_censorship.on_select_mode(move |index| {
+            if let Err(e) = enforce::check(Field::Obfuscation) {
+                tracing::warn!("{e}");
+                return;
+            }
+            let Some(&(mode, _)) = usize::try_from(index).ok().and_then(|i| MODES.get(i)) else {
+                return; // A mode we don't know about, which must already be selected
+            };
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let mut settings = rpc.get_settings().await?.obfuscation_settings;
+                settings.selected_obfuscation = mode;
+                rpc.set_obfuscation_settings(settings).await?;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        anti_censorship.on_set_udp2tcp_port(move |port| {
+            set_port(&rpc, app_weak.clone(), PortSetting::Udp2Tcp, &port);
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        anti_censorship.on_set_shadowsocks_port(move |port| {
+            set_port(&rpc, app_weak.clone(), PortSetting::Shadowsocks, &port);
+        });
+    }
+}
+
+/// Sync the anti-censorship view with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let anti_censorship = app.global::<AntiCensorship>();
+    let obfuscation = &settings.obfuscation_settings;
+
+    let selected = obfuscation.selected_obfuscation;
+    let index = MODES.iter().position(|&(mode, _)| mode == selected);
+    anti_censorship.set_modes(mode_names(index.is_none().then_some(selected)));
+    anti_censorship.set_selected_mode(index.unwrap_or(MODES.len()) as i32);
+
+    anti_censorship.set_udp2tcp_port(ports::to_text(obfuscation.udp2tcp.port));
+    anti_censorship.set_shadowsocks_port(ports::to_text(obfuscation.shadowsocks.port));
+}
+
+/// Show which ports the relays accept.
+pub fn update_relay_list(app: &AppWindow, relay_list: &RelayList) {
+    let anti_censorship = app.global::<AntiCensorship>();
+    anti_censorship.set_udp2tcp_allowed_ports(ports::format_ranges(
+        &PortSetting::Udp2Tcp.allowed_ports(relay_list),
+    ));
+    anti_censorship.set_shadowsocks_allowed_ports(ports::format_ranges(
+        &PortSetting::Shadowsocks.allowed_ports(relay_list),
+    ));
+}
+
+/// Names of the known modes, plus `unknown` if the daemon has selected a mode we don't know of.
+fn mode_names(unknown: Option<SelectedObfuscation>) -> ModelRc<SharedString> {
+    let names: VecModel<SharedString> = MODES
+        .iter()
+        .map(|&(_, name)| name.into())
+        .chain(unknown.map(|mode| format!("{mode:?}").into()))
+        .collect();
+    ModelRc::new(names)
+}
+
+/// Validate `port` against the relay list and save it. An empty port means automatic.
+fn set_port(rpc: &Rpc, app_weak: Weak<AppWindow>, setting: PortSetting, port: &str) {
+    let show_error = move |app_weak: &Weak<AppWindow>, error: String| {
+        let _ = app_weak.upgrade_in_event_loop(move |app| {
+            app.global::<AntiCensorship>().set_error(error.into());
+        });
+    };
+
+    if let Err(e) = policy::check(Field::Obfuscation) {
+        return show_error(&app_weak, e.to_string());
+    }
+
+    let port = match ports::parse(port) {
+        Ok(port) => port,
+        Err(error) => return show_error(&app_weak, error),
+    };
+
+    rpc.spawn_with_rpc(async move |mut rpc| {
+        if let Constraint::Only(port) = port {
+            let relay_list = rpc.get_relay_locations().await?;
+            if let Err(error) = ports::check_allowed(port, &setting.allowed_ports(&relay_list)) {
+                show_error(&app_weak, error);
+                return Ok(());
+            }
+        }
+
+        let mut settings = rpc.get_settings().await?.obfuscation_settings;
+        *setting.port_mut(&mut settings) = port;
+        rpc.set_obfuscation_settings(settings).await?;
+
+        show_error(&app_weak, String::new());
+        Ok(())
+    });
+}
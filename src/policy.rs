@@ -0,0 +1,294 @@
+//! A system-wide admin policy that restricts which settings can be changed in the app.
+//!
+//! The policy is read once on start. The UI hides or locks the restricted settings, and the
+//! callbacks that change them refuse to, whatever input they come from.
+
+use std::{fs, io, path::Path, sync::OnceLock};
+
+use anyhow::Context as _;
+use serde::Deserialize;
+use slint::ComponentHandle as _;
+
+use crate::{
+    enforce::{self, DesiredState},
+    profiles::{Field, Profile},
+    rpc::Rpc,
+    slint_ty::{AppWindow, Policy, SettingPolicy},
+};
+
+/// Where the policy is read from. It should only be writable by administrators, so unlike the
+/// desired-state file, it can't be overridden on the command line.
+pub const PATH: &str = "/etc/mullvad-slint/policy.toml";
+
+static POLICY: OnceLock<Vec<(Field, Restriction)>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restriction {
+    /// Shown, but can't be changed.
+    ReadOnly,
+    /// Not shown, and can't be changed.
+    Hidden,
+    /// Kept at a value set by the policy, and can't be changed.
+    Forced,
+}
+
+/// The policy file, e.g.
+///
+/// ```toml
+/// allow_lan = "read-only"
+/// daita = "hidden"
+/// lockdown_mode = { forced = true }
+/// location = { forced = { country = "se" } }
+/// ```
+///
+/// The forced values are written like in the desired-state file, see [`DesiredState`].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    allow_lan: Option<Rule<bool>>,
+    lockdown_mode: Option<Rule<bool>>,
+    auto_connect: Option<Rule<bool>>,
+    enable_ipv6: Option<Rule<bool>>,
+    daita: Option<Rule<bool>>,
+    obfuscation: Option<Rule<enforce::Obfuscation>>,
+    location: Option<Rule<enforce::Location>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Rule<T> {
+    Lock(Lock),
+    Forced { forced: T },
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Lock {
+    ReadOnly,
+    Hidden,
+}
+
+impl<T> Rule<T> {
+    /// How a rule, if there is one, restricts its setting.
+    fn restriction(rule: &Option<Self>) -> Option<Restriction> {
+        match rule.as_ref()? {
+            Rule::Lock(Lock::ReadOnly) => Some(Restriction::ReadOnly),
+            Rule::Lock(Lock::Hidden) => Some(Restriction::Hidden),
+            Rule::Forced { .. } => Some(Restriction::Forced),
+        }
+    }
+
+    fn forced(self) -> Option<T> {
+        match self {
+            Rule::Lock(_) => None,
+            Rule::Forced { forced } => Some(forced),
+        }
+    }
+}
+
+/// Read the policy at `path`, if there is one, show the restrictions, and start enforcing forced
+/// values.
+pub fn setup(app: &AppWindow, rpc: &Rpc, path: &Path) {
+    if !path.exists() {
+        let _ = POLICY.set(vec![]);
+        return;
+    }
+
+    let (restrictions, forced) = load(path, fs::read_to_string(path));
+    for (field, restriction) in &restrictions {
+        let restriction = match restriction {
+            Restriction::ReadOnly => "read-only",
+            Restriction::Hidden => "hidden",
+            Restriction::Forced => "forced",
+        };
+        tracing::info!("The admin policy makes {} {restriction}", field.label());
+    }
+
+    let ui_policy = |field| match restriction_in(&restrictions, field) {
+        None => SettingPolicy::Editable,
+        Some(Restriction::ReadOnly | Restriction::Forced) => SettingPolicy::ReadOnly,
+        Some(Restriction::Hidden) => SettingPolicy::Hidden,
+    };
+    let policy = app.global::<Policy>();
+    policy.set_allow_lan(ui_policy(Field::AllowLan));
+    policy.set_lockdown_mode(ui_policy(Field::LockdownMode));
+    policy.set_auto_connect(ui_policy(Field::AutoConnect));
+    policy.set_enable_ipv6(ui_policy(Field::EnableIpv6));
+    policy.set_daita(ui_policy(Field::Daita));
+    policy.set_obfuscation(ui_policy(Field::Obfuscation));
+    policy.set_location(ui_policy(Field::Location));
+
+    if !forced.fields().is_empty() {
+        enforce::force(rpc, path.to_owned(), forced);
+    }
+    let _ = POLICY.set(restrictions);
+}
+
+/// The restrictions and forced values of the policy read from `path`.
+///
+/// If the policy can't be read or parsed, every setting it may cover is made read-only rather
+/// than being left unrestricted.
+fn load(path: &Path, policy: io::Result<String>) -> (Vec<(Field, Restriction)>, Profile) {
+    let policy = policy
+        .with_context(|| format!("Failed to read {}", path.display()))
+        .and_then(|policy| {
+            parse(&policy).with_context(|| format!("Failed to parse {}", path.display()))
+        });
+    match policy {
+        Ok(policy) => policy,
+        Err(e) => {
+            tracing::error!("Failed to load the admin policy, locking all settings: {e:#}");
+            let restrictions = Field::ALL
+                .into_iter()
+                .map(|field| (field, Restriction::ReadOnly))
+                .collect();
+            (restrictions, Profile::default())
+        }
+    }
+}
+
+fn parse(policy: &str) -> anyhow::Result<(Vec<(Field, Restriction)>, Profile)> {
+    let policy: PolicyFile = toml::from_str(policy)?;
+
+    let restrictions: Vec<(Field, Restriction)> = [
+        (Field::AllowLan, Rule::restriction(&policy.allow_lan)),
+        (
+            Field::LockdownMode,
+            Rule::restriction(&policy.lockdown_mode),
+        ),
+        (Field::AutoConnect, Rule::restriction(&policy.auto_connect)),
+        (Field::EnableIpv6, Rule::restriction(&policy.enable_ipv6)),
+        (Field::Daita, Rule::restriction(&policy.daita)),
+        (Field::Obfuscation, Rule::restriction(&policy.obfuscation)),
+        (Field::Location, Rule::restriction(&policy.location)),
+    ]
+    .into_iter()
+    .filter_map(|(field, restriction)| Some((field, restriction?)))
+    .collect();
+
+    let forced = DesiredState {
+        allow_lan: policy.allow_lan.and_then(Rule::forced),
+        lockdown_mode: policy.lockdown_mode.and_then(Rule::forced),
+        auto_connect: policy.auto_connect.and_then(Rule::forced),
+        enable_ipv6: policy.enable_ipv6.and_then(Rule::forced),
+        daita: policy.daita.and_then(Rule::forced),
+        obfuscation: policy.obfuscation.and_then(Rule::forced),
+        location: policy.location.and_then(Rule::forced),
+        ..DesiredState::default()
+    }
+    .into_profile()
+    .context("Invalid forced value")?;
+
+    Ok((restrictions, forced))
+}
+
+/// How the policy restricts `field`, if at all.
+pub fn restriction(field: Field) -> Option<Restriction> {
+    restriction_in(POLICY.get()?, field)
+}
+
+fn restriction_in(restrictions: &[(Field, Restriction)], field: Field) -> Option<Restriction> {
+    restrictions
+        .iter()
+        .find(|(restricted, _)| *restricted == field)
+        .map(|&(_, restriction)| restriction)
+}
+
+/// Fail if the policy doesn't let the user change `field`.
+pub fn check(field: Field) -> anyhow::Result<()> {
+    match restriction(field) {
+        None => Ok(()),
+        Some(_) => anyhow::bail!("{} is managed by your organization", field.label()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mullvad_types::{
+        constraints::Constraint,
+        relay_constraints::{GeographicLocationConstraint, LocationConstraint},
+    };
+
+    use super::*;
+
+    #[test]
+    fn read_only_and_hidden() {
+        let (restrictions, forced) = parse(
+            r#"
+            allow_lan = "read-only"
+            daita = "hidden"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            restrictions,
+            [
+                (Field::AllowLan, Restriction::ReadOnly),
+                (Field::Daita, Restriction::Hidden),
+            ]
+        );
+        assert_eq!(
+            restriction_in(&restrictions, Field::AllowLan),
+            Some(Restriction::ReadOnly)
+        );
+        assert_eq!(
+            restriction_in(&restrictions, Field::Daita),
+            Some(Restriction::Hidden)
+        );
+        assert_eq!(restriction_in(&restrictions, Field::Location), None);
+        assert!(forced.fields().is_empty());
+    }
+
+    #[test]
+    fn forced() {
+        let (restrictions, forced) = parse(
+            r#"
+            lockdown_mode = { forced = true }
+            location = { forced = { country = "se" } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            restrictions,
+            [
+                (Field::LockdownMode, Restriction::Forced),
+                (Field::Location, Restriction::Forced),
+            ]
+        );
+        assert_eq!(forced.fields(), [Field::LockdownMode, Field::Location]);
+        assert_eq!(forced.lockdown_mode, Some(true));
+        assert!(
+            forced.location
+                == Some(Constraint::Only(LocationConstraint::Location(
+                    GeographicLocationConstraint::Country("se".to_owned())
+                )))
+        );
+    }
+
+    #[test]
+    fn reject_unknown_keys_and_values() {
+        assert!(parse(r#"split_tunneling = "hidden""#).is_err());
+        assert!(parse(r#"allow_lan = "locked""#).is_err());
+        assert!(parse(r#"allow_lan = { forced = "yes" }"#).is_err());
+        assert!(parse(r#"location = { forced = { city = "got" } }"#).is_err());
+    }
+
+    #[test]
+    fn lock_everything_on_error() {
+        let locked: Vec<(Field, Restriction)> = Field::ALL
+            .into_iter()
+            .map(|field| (field, Restriction::ReadOnly))
+            .collect();
+        let path = Path::new("policy.toml");
+
+        let (restrictions, forced) = load(path, Ok("allow_lan = ".to_owned()));
+        assert_eq!(restrictions, locked);
+        assert!(forced.fields().is_empty());
+
+        let (restrictions, forced) = load(path, Err(io::ErrorKind::PermissionDenied.into()));
+        assert_eq!(restrictions, locked);
+        assert!(forced.fields().is_empty());
+    }
+}
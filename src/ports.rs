@@ -0,0 +1,97 @@
+//! Parsing and validation of user-entered ports.
+
+use std::ops::RangeInclusive;
+
+use mullvad_types::constraints::Constraint;
+use slint::SharedString;
+
+/// Parse a port entered by the user. An empty string means automatic.
+pub fn parse(port: &str) -> Result<Constraint<u16>, String> {
+    match port.trim() {
+        "" => Ok(Constraint::Any),
+        port => port
+            .parse()
+            .map(Constraint::Only)
+            .map_err(|_| format!("\"{port}\" is not a valid port.")),
+    }
+}
+
+/// Check that `port` falls within one of `allowed` ranges.
+pub fn check_allowed(port: u16, allowed: &[RangeInclusive<u16>]) -> Result<(), String> {
+    if allowed.iter().any(|range| range.contains(&port)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Port {port} is not allowed. Allowed ports: {}",
+            format_ranges(allowed)
+        ))
+    }
+}
+
+/// Format a port constraint for a text field, where automatic is empty.
+pub fn to_text(port: Constraint<u16>) -> SharedString {
+    match port {
+        Constraint::Any => SharedString::new(),
+        Constraint::Only(port) => port.to_string().into(),
+    }
+}
+
+/// Format port ranges for display, e.g. `80, 443, 5000-5500`.
+pub fn format_ranges(ranges: &[RangeInclusive<u16>]) -> SharedString {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{}-{}", range.start(), range.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ports() {
+        assert_eq!(parse(""), Ok(Constraint::Any));
+        assert_eq!(parse("  "), Ok(Constraint::Any));
+        assert_eq!(parse(" 51820 "), Ok(Constraint::Only(51820)));
+        assert_eq!(parse("65535"), Ok(Constraint::Only(65535)));
+        assert!(parse("65536").is_err());
+        assert!(parse("-1").is_err());
+        assert!(parse("http").is_err());
+    }
+
+    #[test]
+    fn allowed_ports() {
+        let allowed = [53..=53, 4000..=33433];
+        assert_eq!(check_allowed(53, &allowed), Ok(()));
+        assert_eq!(check_allowed(4000, &allowed), Ok(()));
+        assert_eq!(check_allowed(33433, &allowed), Ok(()));
+        assert_eq!(
+            check_allowed(54, &allowed),
+            Err("Port 54 is not allowed. Allowed ports: 53, 4000-33433".to_owned())
+        );
+        assert!(check_allowed(53, &[]).is_err());
+    }
+
+    #[test]
+    fn format_port_ranges() {
+        assert_eq!(format_ranges(&[]), "");
+        assert_eq!(
+            format_ranges(&[80..=80, 443..=443, 5000..=5500]),
+            "80, 443, 5000-5500"
+        );
+    }
+
+    #[test]
+    fn port_text() {
+        assert_eq!(to_text(Constraint::Any), "");
+        assert_eq!(to_text(Constraint::Only(53)), "53");
+    }
+}
@@ -0,0 +1,528 @@
+//! GUI preferences, which the daemon doesn't know about, saved in
+//! `<config_home>/mullvad-slint/preferences.json`.
+//!
+//! The file is versioned. Older versions are migrated when loaded, and fields written by a newer
+//! version of the app are kept as they are. Writes are atomic, and a file that can't be parsed is
+//! set aside rather than overwritten.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use slint::{
+    CloseRequestResponse, ComponentHandle as _, LogicalSize, Model as _, ModelRc, VecModel, Weak,
+};
+
+use crate::slint_ty::{self, AppWindow, Preferences, RelayList, Route, View};
+
+/// The version of the preferences file that this app writes.
+const VERSION: u64 = 1;
+
+/// Migrations to the next version, starting from version 1. `MIGRATIONS[0]` upgrades a version 1
+/// file to version 2, and so on, so there must be `VERSION - 1` of them.
+const MIGRATIONS: &[Migration] = &[];
+const _: () = assert!(MIGRATIONS.len() as u64 == VERSION - 1);
+
+type Migration = fn(&mut Map<String, Value>);
+
+static STORE: Mutex<Option<Store>> = Mutex::new(None);
+
+struct Store {
+    path: PathBuf,
+    prefs: Prefs,
+    /// Whether the file may be written. It's not if it exists but couldn't be read.
+    writable: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Prefs {
+    version: u64,
+    window_size: Option<WindowSize>,
+    /// Views that were open when the app was closed, e.g. `["settings", "vpn-settings"]`.
+    open_views: Vec<String>,
+    update_notifications: bool,
+    dismissed_update: Option<String>,
+    favourites: Vec<Favourite>,
+    /// Fields written by a newer version of the app, which are kept when saving.
+    #[serde(flatten)]
+    unknown: Map<String, Value>,
+}
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            window_size: None,
+            open_views: vec![],
+            update_notifications: true,
+            dismissed_update: None,
+            favourites: vec![],
+            unknown: Map::new(),
+        }
+    }
+}
+
+/// Window size in logical pixels.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct WindowSize {
+    width: f32,
+    height: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Favourite {
+    country: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    city: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    hostname: String,
+    name: String,
+}
+
+impl Store {
+    /// Load the preferences, or start from the defaults if there are none.
+    ///
+    /// A file that can't be parsed is renamed to e.g. `preferences.json.corrupt-1767225600` first,
+    /// so that it's not lost when the defaults are saved.
+    fn load(path: PathBuf) -> Self {
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Self::new(path, Prefs::default(), true);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Failed to read {}, not saving preferences: {e}",
+                    path.display()
+                );
+                return Self::new(path, Prefs::default(), false);
+            }
+        };
+
+        match parse(&json) {
+            Ok(prefs) => Self::new(path, prefs, true),
+            Err(e) => {
+                let backup = backup_path(&path);
+                tracing::error!(
+                    "Failed to parse {}, moving it to {}: {e:#}",
+                    path.display(),
+                    backup.display()
+                );
+                let writable = match fs::rename(&path, &backup) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        tracing::error!("Failed to move {}: {e}", path.display());
+                        false
+                    }
+                };
+                Self::new(path, Prefs::default(), writable)
+            }
+        }
+    }
+
+    fn new(path: PathBuf, prefs: Prefs, writable: bool) -> Self {
+        Self {
+            path,
+            prefs,
+            writable,
+        }
+    }
+
+    /// Write the preferences to a temporary file, and move it in place of the old one.
+    fn save(&self) -> anyhow::Result<()> {
+        if !self.writable {
+            return Ok(());
+        }
+
+        let json = serde_json::to_string_pretty(&self.prefs)?;
+        let dir = self.path.parent().context("No parent directory")?;
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        write_atomically(&self.path, &(json + "\n"))
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+/// Write `contents` to a temporary file next to `path`, and move it in place of `path`, so that
+/// it's never left half-written.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Where to set a corrupt preferences file aside, without replacing an earlier one.
+fn backup_path(path: &Path) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    let mut backup = path.with_extension(format!("json.corrupt-{now}"));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_extension(format!("json.corrupt-{now}-{n}"));
+        n += 1;
+    }
+    backup
+}
+
+/// Parse the preferences file, migrating it from an older version if needed.
+fn parse(json: &str) -> anyhow::Result<Prefs> {
+    let mut prefs: Map<String, Value> = serde_json::from_str(json)?;
+    migrate(&mut prefs, MIGRATIONS)?;
+    Ok(serde_json::from_value(Value::Object(prefs))?)
+}
+
+/// Run the `migrations` that `prefs` hasn't been through yet.
+fn migrate(prefs: &mut Map<String, Value>, migrations: &[Migration]) -> anyhow::Result<()> {
+    let version = match prefs.get("version") {
+        Some(version) => version.as_u64().context("Invalid version")?,
+        None => 1,
+    };
+
+    // A newer version is read as far as we understand it, and keeps its version number.
+    let first_migration = usize::try_from(version.saturating_sub(1)).unwrap_or(usize::MAX);
+    for (i, migration) in migrations.iter().enumerate().skip(first_migration) {
+        migration(prefs);
+        tracing::info!("Migrated preferences from version {}", i + 1);
+    }
+    let latest = migrations.len() as u64 + 1;
+    prefs.insert("version".to_owned(), Value::from(version.max(latest)));
+    Ok(())
+}
+
+/// Load the preferences, restore the window, and install callbacks for changing them.
+///
+/// `config_home` is the XDG config directory, e.g. `~/.config`.
+pub fn setup(app: &AppWindow, config_home: &Path) {
+    let store = Store::load(config_home.join("mullvad-slint").join("preferences.json"));
+
+    if let Some(WindowSize { width, height }) = store.prefs.window_size {
+        app.window().set_size(LogicalSize::new(width, height));
+    }
+    let route = app.global::<Route>();
+    for view in &store.prefs.open_views {
+        open_view(&route, view);
+    }
+
+    *STORE.lock().unwrap() = Some(store);
+    update_ui(app);
+
+    let preferences = app.global::<Preferences>();
+    preferences.set_available(true);
+    {
+        let app_weak = app.as_weak();
+        preferences.on_set_update_notifications(move |enabled| {
+            update(&app_weak, |prefs| prefs.update_notifications = enabled);
+        });
+    }
+
+    {
+        let app_weak = app.as_weak();
+        preferences.on_dismiss_update(move |version| {
+            update(&app_weak, |prefs| {
+                prefs.dismissed_update = Some(version.into());
+            });
+        });
+    }
+
+    {
+        let app_weak = app.as_weak();
+        preferences.on_toggle_favourite(move |favourite| {
+            let favourite = Favourite {
+                country: favourite.country.into(),
+                city: favourite.city.into(),
+                hostname: favourite.hostname.into(),
+                name: favourite.name.into(),
+            };
+            update(&app_weak, |prefs| {
+                let before = prefs.favourites.len();
+                prefs
+                    .favourites
+                    .retain(|existing| !existing.is_same(&favourite));
+                if prefs.favourites.len() == before {
+                    prefs.favourites.push(favourite);
+                }
+            });
+        });
+    }
+
+    // Remember the window size and open views when the window is closed.
+    let app_weak = app.as_weak();
+    app.window().on_close_requested(move || {
+        if let Some(app) = app_weak.upgrade() {
+            let size = app.window().size().to_logical(app.window().scale_factor());
+            let open_views = open_views(&app.global::<Route>());
+            update(&app_weak, |prefs| {
+                prefs.window_size = Some(WindowSize {
+                    width: size.width,
+                    height: size.height,
+                });
+                prefs.open_views = open_views;
+            });
+        }
+        CloseRequestResponse::HideWindow
+    });
+}
+
+impl Favourite {
+    /// Whether both are the same location. Names are not compared.
+    fn is_same(&self, other: &Favourite) -> bool {
+        (&self.country, &self.city, &self.hostname)
+            == (&other.country, &other.city, &other.hostname)
+    }
+}
+
+/// Change the preferences, save them, and show the change.
+fn update(app_weak: &Weak<AppWindow>, f: impl FnOnce(&mut Prefs)) {
+    {
+        let mut store = STORE.lock().unwrap();
+        let Some(store) = store.as_mut() else {
+            return;
+        };
+        f(&mut store.prefs);
+        if let Err(e) = store.save() {
+            tracing::error!("Failed to save preferences: {e:#}");
+        }
+    }
+
+    if let Some(app) = app_weak.upgrade() {
+        update_ui(&app);
+    }
+}
+
+fn update_ui(app: &AppWindow) {
+    let store = STORE.lock().unwrap();
+    let Some(store) = store.as_ref() else {
+        return;
+    };
+    let prefs = &store.prefs;
+
+    let favourites: VecModel<_> = prefs
+        .favourites
+        .iter()
+        .map(|favourite| slint_ty::Favourite {
+            country: favourite.country.as_str().into(),
+            city: favourite.city.as_str().into(),
+            hostname: favourite.hostname.as_str().into(),
+            name: favourite.name.as_str().into(),
+        })
+        .collect();
+
+    let preferences = app.global::<Preferences>();
+    preferences.set_update_notifications(prefs.update_notifications);
+    preferences.set_dismissed_update(prefs.dismissed_update.clone().unwrap_or_default().into());
+    preferences.set_favourites(ModelRc::new(favourites));
+    drop(store);
+
+    mark_favourites(app);
+    #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+    crate::app_info::update_tray(app);
+}
+
+/// Mark the favourite countries, cities and relays in the relay list.
+pub fn mark_favourites(app: &AppWindow) {
+    let favourites: HashSet<(String, String, String)> = app
+        .global::<Preferences>()
+        .get_favourites()
+        .iter()
+        .map(|favourite| {
+            (
+                favourite.country.into(),
+                favourite.city.into(),
+                favourite.hostname.into(),
+            )
+        })
+        .collect();
+    let is_favourite = |country: &str, city: &str, hostname: &str| {
+        favourites.contains(&(country.to_owned(), city.to_owned(), hostname.to_owned()))
+    };
+
+    // Filtered countries share their city and relay models with the full list, but not the
+    // countries themselves.
+    let relay_list = app.global::<RelayList>();
+    for countries in [
+        relay_list.get_countries(),
+        relay_list.get_filtered_countries(),
+    ] {
+        for (row, mut country) in countries.iter().enumerate() {
+            let favourite = is_favourite(&country.code, "", "");
+            if country.favourite != favourite {
+                country.favourite = favourite;
+                countries.set_row_data(row, country.clone());
+            }
+
+            let cities = &country.cities;
+            for (row, mut city) in cities.iter().enumerate() {
+                let favourite = is_favourite(&country.code, &city.code, "");
+                if city.favourite != favourite {
+                    city.favourite = favourite;
+                    cities.set_row_data(row, city.clone());
+                }
+
+                let relays = &city.relays;
+                for (row, mut relay) in relays.iter().enumerate() {
+                    let favourite = is_favourite(&country.code, &city.code, &relay.hostname);
+                    if relay.favourite != favourite {
+                        relay.favourite = favourite;
+                        relays.set_row_data(row, relay);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Views that are restored on start, by name.
+///
+/// Views that show transient state, such as the connection to the daemon or a half-filled form,
+/// are left out.
+fn open_views(route: &Route) -> Vec<String> {
+    let views = [
+        ("settings", route.get_settings()),
+        ("daita", route.get_daita()),
+        ("multihop", route.get_multihop()),
+        ("vpn-settings", route.get_vpn_settings()),
+        ("anti-censorship", route.get_anti_censorship()),
+        ("custom-dns", route.get_custom_dns()),
+        ("dns-blockers", route.get_dns_blockers()),
+        ("wireguard-key", route.get_wireguard_key()),
+        ("api-access", route.get_api_access()),
+        ("relay-overrides", route.get_relay_overrides()),
+        ("problem-report", route.get_problem_report()),
+        ("app-info", route.get_app_info()),
+        ("settings-transfer", route.get_settings_transfer()),
+        ("profiles", route.get_profiles()),
+        ("split-tunneling", route.get_split_tunneling()),
+        ("select-location", route.get_select_location()),
+    ];
+    views
+        .into_iter()
+        .filter(|(_, view)| view.show)
+        .map(|(name, _)| name.to_owned())
+        .collect()
+}
+
+fn open_view(route: &Route, name: &str) {
+    let show = View { show: true };
+    match name {
+        "settings" => route.set_settings(show),
+        "daita" => route.set_daita(show),
+        "multihop" => route.set_multihop(show),
+        "vpn-settings" => route.set_vpn_settings(show),
+        "anti-censorship" => route.set_anti_censorship(show),
+        "custom-dns" => route.set_custom_dns(show),
+        "dns-blockers" => route.set_dns_blockers(show),
+        "wireguard-key" => route.set_wireguard_key(show),
+        "api-access" => route.set_api_access(show),
+        "relay-overrides" => route.set_relay_overrides(show),
+        "problem-report" => route.set_problem_report(show),
+        "app-info" => route.set_app_info(show),
+        "settings-transfer" => route.set_settings_transfer(show),
+        "profiles" => route.set_profiles(show),
+        "split-tunneling" => route.set_split_tunneling(show),
+        "select-location" => route.set_select_location(show),
+        _ => tracing::warn!("Not restoring unknown view \"{name}\""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Version 1 to 2: rename `old_name` to `new_name`.
+    fn rename(prefs: &mut Map<String, Value>) {
+        if let Some(value) = prefs.remove("old_name") {
+            prefs.insert("new_name".to_owned(), value);
+        }
+    }
+
+    /// Version 2 to 3: add `added`.
+    fn add(prefs: &mut Map<String, Value>) {
+        prefs.insert("added".to_owned(), Value::from(true));
+    }
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(object) => object,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn migrate_from_each_version() {
+        let migrations: &[Migration] = &[rename, add];
+
+        let mut prefs = object(json!({ "old_name": 1 }));
+        migrate(&mut prefs, migrations).unwrap();
+        assert_eq!(
+            Value::Object(prefs),
+            json!({ "version": 3, "new_name": 1, "added": true })
+        );
+
+        let mut prefs = object(json!({ "version": 2, "old_name": 1 }));
+        migrate(&mut prefs, migrations).unwrap();
+        assert_eq!(
+            Value::Object(prefs),
+            json!({ "version": 3, "old_name": 1, "added": true })
+        );
+
+        let mut prefs = object(json!({ "version": 3 }));
+        migrate(&mut prefs, migrations).unwrap();
+        assert_eq!(Value::Object(prefs), json!({ "version": 3 }));
+    }
+
+    #[test]
+    fn keep_newer_version_and_unknown_fields() {
+        let json = r#"{ "version": 7, "update_notifications": false, "future": { "x": 1 } }"#;
+        let prefs = parse(json).unwrap();
+        assert_eq!(prefs.version, 7);
+        assert!(!prefs.update_notifications);
+
+        let saved = serde_json::to_value(&prefs).unwrap();
+        assert_eq!(saved["version"], json!(7));
+        assert_eq!(saved["future"], json!({ "x": 1 }));
+    }
+
+    #[test]
+    fn reject_corrupt_input() {
+        assert!(parse("").is_err());
+        assert!(parse("{").is_err());
+        assert!(parse("[]").is_err());
+        assert!(parse(r#"{ "version": "two" }"#).is_err());
+        assert!(parse(r#"{ "favourites": 3 }"#).is_err());
+    }
+
+    #[test]
+    fn keep_every_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("preferences.json");
+
+        for corrupt in ["{", "["] {
+            fs::write(&path, corrupt).unwrap();
+            let store = Store::load(path.clone());
+            assert!(store.writable);
+            assert!(store.prefs.favourites.is_empty());
+        }
+
+        let mut backups: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        backups.sort();
+        assert_eq!(backups, ["[", "{"]);
+    }
+}
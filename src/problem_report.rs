@@ -0,0 +1,393 @@
+//! Problem reports: logs from the daemon and this app, redacted and sent to Mullvad support.
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{Context as _, bail};
+use slint::{ComponentHandle as _, Weak};
+
+use crate::{
+    RT,
+    rpc::Rpc,
+    settings_transfer,
+    slint_ty::{AppWindow, ProblemReport},
+};
+
+/// The most recent output of this app's tracing subscriber.
+static APP_LOG: Mutex<VecDeque<u8>> = Mutex::new(VecDeque::new());
+
+/// How much of each log to include in a report.
+const MAX_LOG_SIZE: usize = 512 * 1024;
+
+/// Where the daemon writes its log.
+#[cfg(not(target_os = "windows"))]
+const DAEMON_LOG: &str = "/var/log/mullvad-vpn/daemon.log";
+#[cfg(target_os = "windows")]
+const DAEMON_LOG: &str = r"C:\ProgramData\Mullvad VPN\daemon.log";
+
+/// Tool installed alongside the daemon, which sends reports to Mullvad support.
+const PROBLEM_REPORT_TOOL: &str = "mullvad-problem-report";
+
+const REDACTED: &str = "[REDACTED]";
+const REDACTED_ACCOUNT_NUMBER: &str = "[REDACTED ACCOUNT NUMBER]";
+
+/// Writes to stderr, and keeps a copy of the output for problem reports.
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut log = APP_LOG.lock().unwrap();
+        log.extend(buf);
+        let excess = log.len().saturating_sub(MAX_LOG_SIZE);
+        log.drain(..excess);
+
+        io::stderr().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+/// For use with [`tracing_subscriber::fmt::SubscriberBuilder::with_writer`].
+pub fn log_writer() -> LogWriter {
+    LogWriter
+}
+
+/// Install callbacks for the problem report view.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let problem_report = app.global::<ProblemReport>();
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        problem_report.on_collect(move || {
+            if let Some(app) = app_weak.upgrade() {
+                app.global::<ProblemReport>().set_collecting(true);
+            }
+
+            let rpc = rpc.clone();
+            let app_weak = app_weak.clone();
+            RT.spawn(async move {
+                let daemon_version = rpc
+                    .with_rpc(async move |mut rpc| Ok(rpc.get_current_version().await?))
+                    .await;
+                let report = tokio::task::spawn_blocking(move || collect(daemon_version))
+                    .await
+                    .unwrap_or_else(|e| format!("Failed to collect logs: {e}"));
+
+                let _ = app_weak.upgrade_in_event_loop(move |app| {
+                    let problem_report = app.global::<ProblemReport>();
+                    problem_report.set_report(report.into());
+                    problem_report.set_collecting(false);
+                });
+            });
+        });
+    }
+
+    {
+        let app_weak = app.as_weak();
+        problem_report.on_save(move |path| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let problem_report = app.global::<ProblemReport>();
+            let report = with_user_input(
+                &problem_report.get_email(),
+                &problem_report.get_message(),
+                &problem_report.get_report(),
+            );
+
+            let path = PathBuf::from(path.trim());
+            // Like the report that is sent, it may include details the user wants to keep private.
+            match settings_transfer::write_private(&path, &report) {
+                Ok(()) => {
+                    problem_report.set_status(format!("Saved to {}", path.display()).into());
+                    problem_report.set_error("".into());
+                }
+                Err(e) => {
+                    problem_report.set_status("".into());
+                    problem_report.set_error(format!("Failed to save report: {e}").into());
+                }
+            }
+        });
+    }
+
+    {
+        let app_weak = app.as_weak();
+        problem_report.on_send(move || {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let problem_report = app.global::<ProblemReport>();
+            problem_report.set_sending(true);
+            problem_report.set_status("".into());
+            problem_report.set_error("".into());
+
+            let email = problem_report.get_email().to_string();
+            let message = problem_report.get_message().to_string();
+            let report = problem_report.get_report().to_string();
+            let app_weak = app_weak.clone();
+            RT.spawn(async move {
+                let result = match tokio::task::spawn_blocking(move || {
+                    send(&email, &message, &report)
+                })
+                .await
+                {
+                    Ok(result) => result,
+                    Err(e) => Err(e.into()),
+                };
+                show_send_result(&app_weak, result);
+            });
+        });
+    }
+}
+
+fn show_send_result(app_weak: &Weak<AppWindow>, result: anyhow::Result<()>) {
+    if let Err(e) = &result {
+        tracing::error!("Failed to send problem report: {e:#}");
+    }
+
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let problem_report = app.global::<ProblemReport>();
+        problem_report.set_sending(false);
+        match result {
+            Ok(()) => {
+                problem_report.set_status("Thanks! The report was sent.".into());
+                problem_report.set_message("".into());
+            }
+            Err(e) => problem_report.set_error(format!("Failed to send report: {e:#}").into()),
+        }
+    });
+}
+
+/// Collect and redact the logs of the daemon and this app.
+fn collect(daemon_version: anyhow::Result<String>) -> String {
+    let mut report = format!(
+        "Mullvad VPN (Slint) {} on {} {}\n",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH,
+    );
+    match daemon_version {
+        Ok(version) => report += &format!("Daemon version: {version}\n"),
+        Err(e) => report += &format!("The daemon is not reachable: {e:#}\n"),
+    }
+
+    report += &format!("\n=== Daemon log ({DAEMON_LOG}) ===\n");
+    match read_tail(Path::new(DAEMON_LOG)) {
+        Ok(log) => report += &log,
+        Err(e) => report += &format!("The daemon log is unavailable: {e}\n"),
+    }
+
+    report += "\n=== App log ===\n";
+    let app_log = APP_LOG.lock().unwrap().iter().copied().collect::<Vec<u8>>();
+    report += &strip_ansi(&String::from_utf8_lossy(&app_log));
+
+    redact(&report)
+}
+
+/// Read at most [`MAX_LOG_SIZE`] bytes from the end of a file.
+fn read_tail(path: &Path) -> io::Result<String> {
+    let log = fs::read(path)?;
+    let start = log.len().saturating_sub(MAX_LOG_SIZE);
+    Ok(String::from_utf8_lossy(&log[start..]).into_owned())
+}
+
+/// Prepend the user's email address and message to the report.
+fn with_user_input(email: &str, message: &str, report: &str) -> String {
+    format!("Email: {email}\nMessage:\n{message}\n\n{report}")
+}
+
+/// Send a report using [`PROBLEM_REPORT_TOOL`].
+fn send(email: &str, message: &str, report: &str) -> anyhow::Result<()> {
+    // Only readable by the user, and removed when dropped.
+    let mut file = tempfile::Builder::new()
+        .prefix("mullvad-slint-report-")
+        .suffix(".log")
+        .tempfile()
+        .context("Failed to create report file")?;
+    file.write_all(report.as_bytes())
+        .context("Failed to write report")?;
+
+    let output = Command::new(PROBLEM_REPORT_TOOL)
+        .arg("send")
+        .arg("--report")
+        .arg(file.path())
+        .arg("--email")
+        .arg(email)
+        .arg("--message")
+        .arg(message)
+        .output();
+    drop(file);
+
+    let output = output.with_context(|| format!("Failed to run {PROBLEM_REPORT_TOOL}"))?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Remove terminal color codes.
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip e.g. `ESC [ 1 ; 32 m`
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// Remove account numbers and IP addresses.
+fn redact(text: &str) -> String {
+    redact_ip_addresses(&redact_account_numbers(text))
+}
+
+fn redact_account_numbers(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut redacted = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let at_boundary = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        match account_number_len(&bytes[i..]) {
+            Some(len) if at_boundary => {
+                redacted.push_str(&text[copied..i]);
+                redacted.push_str(REDACTED_ACCOUNT_NUMBER);
+                i += len;
+                copied = i;
+            }
+            _ => i += 1,
+        }
+    }
+    redacted.push_str(&text[copied..]);
+    redacted
+}
+
+/// Length of the account number at the start of `s`, if any. Account numbers are 16 digits,
+/// optionally written in groups of four.
+fn account_number_len(s: &[u8]) -> Option<usize> {
+    let digits = |start: usize, len: usize| {
+        s.get(start..start + len)
+            .is_some_and(|digits| digits.iter().all(u8::is_ascii_digit))
+    };
+    let ends_at = |end: usize| !s.get(end).is_some_and(u8::is_ascii_alphanumeric);
+
+    if digits(0, 16) && ends_at(16) {
+        return Some(16);
+    }
+
+    let grouped = (0..4).all(|group| digits(group * 5, 4))
+        && (0..3).all(|group| s.get(group * 5 + 4) == Some(&b' '));
+    (grouped && ends_at(19)).then_some(19)
+}
+
+fn redact_ip_addresses(text: &str) -> String {
+    let is_address_char = |c: char| c.is_ascii_hexdigit() || c == ':' || c == '.';
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_address_char) {
+        redacted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_address_char(c)).unwrap_or(rest.len());
+        let (token, tail) = rest.split_at(end);
+        redacted.push_str(&redact_address(token));
+        rest = tail;
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Redact `token` if it's an IP address, or an IPv4 address with a port.
+fn redact_address(token: &str) -> String {
+    if !token.contains(|c: char| c.is_ascii_digit()) {
+        return token.to_owned();
+    }
+
+    // Punctuation may follow the address, e.g. at the end of a sentence. IPv6 addresses can end
+    // in `:` themselves, so only strip it if the address doesn't parse.
+    let mut address = token;
+    loop {
+        let suffix = &token[address.len()..];
+        if address.parse::<IpAddr>().is_ok() {
+            return format!("{REDACTED}{suffix}");
+        }
+        if let Some((ip, port)) = address.rsplit_once(':')
+            && ip.parse::<Ipv4Addr>().is_ok()
+            && port.parse::<u16>().is_ok()
+        {
+            return format!("{REDACTED}:{port}{suffix}");
+        }
+        match address.strip_suffix(['.', ':']) {
+            Some(stripped) => address = stripped,
+            None => return token.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_ipv4() {
+        assert_eq!(
+            redact("from 10.0.0.1 to 192.168.1.1"),
+            "from [REDACTED] to [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn redact_ipv6() {
+        assert_eq!(redact("via 2001:db8::1 now"), "via [REDACTED] now");
+        assert_eq!(redact("via fe80:: now"), "via [REDACTED] now");
+        assert_eq!(redact("via 2001:db8:: now"), "via [REDACTED] now");
+        assert_eq!(redact("via ::1 now"), "via [REDACTED] now");
+    }
+
+    #[test]
+    fn redact_address_with_port() {
+        assert_eq!(
+            redact("relay 185.65.134.66:51820"),
+            "relay [REDACTED]:51820"
+        );
+    }
+
+    #[test]
+    fn redact_address_before_punctuation() {
+        assert_eq!(redact("Connected to 10.0.0.1."), "Connected to [REDACTED].");
+        assert_eq!(redact("Endpoint 10.0.0.1: up"), "Endpoint [REDACTED]: up");
+        assert_eq!(redact("Gateway fe80::."), "Gateway [REDACTED].");
+        assert_eq!(redact("Gateway 2001:db8:::"), "Gateway [REDACTED]:");
+        assert_eq!(redact("Relay 10.0.0.1:53."), "Relay [REDACTED]:53.");
+    }
+
+    #[test]
+    fn keep_other_text() {
+        let text = "Version 2026.1, tunnel state: connected, 12:30:00, deadbeef";
+        assert_eq!(redact(text), text);
+    }
+
+    #[test]
+    fn redact_account_number() {
+        assert_eq!(
+            redact("account 1234567890123456 and 1234 5678 9012 3456"),
+            "account [REDACTED ACCOUNT NUMBER] and [REDACTED ACCOUNT NUMBER]"
+        );
+        assert_eq!(redact("id 12345678901234567"), "id 12345678901234567");
+    }
+}
@@ -0,0 +1,733 @@
+//! Named settings profiles: subsets of the daemon settings that are applied in one go.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context as _;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::{
+    constraints::Constraint,
+    relay_constraints::{
+        GeographicLocationConstraint, LocationConstraint, RelaySettings, SelectedObfuscation,
+    },
+    settings::Settings,
+};
+use serde::{Deserialize, Serialize};
+use slint::{ComponentHandle as _, Model as _, ModelRc, VecModel, Weak};
+
+use crate::{
+    enforce, preferences,
+    rpc::{self, Rpc},
+    slint_ty::{self, AppWindow, Profiles},
+};
+
+/// The saved profiles. `None` until [`setup`] has loaded them.
+static STORE: Mutex<Option<Store>> = Mutex::new(None);
+
+/// A named subset of the daemon settings. Settings that are `None` are left alone when applying.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allow_lan: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lockdown_mode: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_connect: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_ipv6: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daita: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscation: Option<SelectedObfuscation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Constraint<LocationConstraint>>,
+}
+
+/// A setting that a profile may capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    AllowLan,
+    LockdownMode,
+    AutoConnect,
+    EnableIpv6,
+    Daita,
+    Obfuscation,
+    Location,
+}
+
+impl Field {
+    /// All fields, in the order they're listed in the UI.
+    pub const ALL: [Field; 7] = [
+        Field::AllowLan,
+        Field::LockdownMode,
+        Field::AutoConnect,
+        Field::EnableIpv6,
+        Field::Daita,
+        Field::Obfuscation,
+        Field::Location,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::AllowLan => "Local network sharing",
+            Field::LockdownMode => "Lockdown mode",
+            Field::AutoConnect => "Auto-connect",
+            Field::EnableIpv6 => "In-tunnel IPv6",
+            Field::Daita => "DAITA",
+            Field::Obfuscation => "Obfuscation",
+            Field::Location => "Location",
+        }
+    }
+
+    pub fn is_set(self, profile: &Profile) -> bool {
+        match self {
+            Field::AllowLan => profile.allow_lan.is_some(),
+            Field::LockdownMode => profile.lockdown_mode.is_some(),
+            Field::AutoConnect => profile.auto_connect.is_some(),
+            Field::EnableIpv6 => profile.enable_ipv6.is_some(),
+            Field::Daita => profile.daita.is_some(),
+            Field::Obfuscation => profile.obfuscation.is_some(),
+            Field::Location => profile.location.is_some(),
+        }
+    }
+
+    /// Copy this setting from `settings` into `profile`.
+    fn capture(self, profile: &mut Profile, settings: &Settings) {
+        match self {
+            Field::AllowLan => profile.allow_lan = Some(settings.allow_lan),
+            Field::LockdownMode => profile.lockdown_mode = Some(settings.lockdown_mode),
+            Field::AutoConnect => profile.auto_connect = Some(settings.auto_connect),
+            Field::EnableIpv6 => {
+                profile.enable_ipv6 = Some(settings.tunnel_options.generic.enable_ipv6);
+            }
+            Field::Daita => {
+                profile.daita = Some(settings.tunnel_options.wireguard.daita.enabled);
+            }
+            Field::Obfuscation => {
+                profile.obfuscation = Some(settings.obfuscation_settings.selected_obfuscation);
+            }
+            // There is no location to capture while a custom server is in use.
+            Field::Location => profile.location = location(settings).cloned(),
+        }
+    }
+
+    /// Remove this setting from `profile`.
+    fn unset(self, profile: &mut Profile) {
+        match self {
+            Field::AllowLan => profile.allow_lan = None,
+            Field::LockdownMode => profile.lockdown_mode = None,
+            Field::AutoConnect => profile.auto_connect = None,
+            Field::EnableIpv6 => profile.enable_ipv6 = None,
+            Field::Daita => profile.daita = None,
+            Field::Obfuscation => profile.obfuscation = None,
+            Field::Location => profile.location = None,
+        }
+    }
+
+    /// Format this setting in `profile`, e.g. `true` or `se, got`.
+    fn value(self, profile: &Profile) -> String {
+        fn show<T: std::fmt::Debug>(value: &Option<T>) -> String {
+            value
+                .as_ref()
+                .map_or_else(|| "unset".to_owned(), |value| format!("{value:?}"))
+        }
+
+        match self {
+            Field::AllowLan => show(&profile.allow_lan),
+            Field::LockdownMode => show(&profile.lockdown_mode),
+            Field::AutoConnect => show(&profile.auto_connect),
+            Field::EnableIpv6 => show(&profile.enable_ipv6),
+            Field::Daita => show(&profile.daita),
+            Field::Obfuscation => show(&profile.obfuscation),
+            Field::Location => match &profile.location {
+                None => "unset".to_owned(),
+                Some(Constraint::Any) => "any".to_owned(),
+                Some(Constraint::Only(LocationConstraint::Location(location))) => match location {
+                    GeographicLocationConstraint::Country(country) => country.clone(),
+                    GeographicLocationConstraint::City(country, city) => {
+                        format!("{country}, {city}")
+                    }
+                    GeographicLocationConstraint::Hostname(_, _, hostname) => hostname.clone(),
+                },
+                Some(Constraint::Only(LocationConstraint::CustomList { .. })) => {
+                    "a custom list".to_owned()
+                }
+            },
+        }
+    }
+
+    /// Describe how `settings` differs from `profile` on this setting, e.g.
+    /// `Lockdown mode: false → true`.
+    pub fn describe_drift(self, profile: &Profile, settings: &Settings) -> String {
+        let mut live = Profile::default();
+        self.capture(&mut live, settings);
+        format!(
+            "{}: {} → {}",
+            self.label(),
+            self.value(&live),
+            self.value(profile)
+        )
+    }
+
+    /// Whether `settings` agrees with `profile` on this setting. Unset fields always agree.
+    fn matches(self, profile: &Profile, settings: &Settings) -> bool {
+        let mut live = Profile::default();
+        if self.is_set(profile) {
+            self.capture(&mut live, settings);
+        }
+        match self {
+            Field::AllowLan => live.allow_lan == profile.allow_lan,
+            Field::LockdownMode => live.lockdown_mode == profile.lockdown_mode,
+            Field::AutoConnect => live.auto_connect == profile.auto_connect,
+            Field::EnableIpv6 => live.enable_ipv6 == profile.enable_ipv6,
+            Field::Daita => live.daita == profile.daita,
+            Field::Obfuscation => live.obfuscation == profile.obfuscation,
+            Field::Location => live.location == profile.location,
+        }
+    }
+}
+
+impl Profile {
+    /// Capture the given settings from `settings`.
+    pub fn capture(name: String, fields: &[Field], settings: &Settings) -> Self {
+        let mut profile = Profile {
+            name,
+            ..Profile::default()
+        };
+        for field in fields {
+            field.capture(&mut profile, settings);
+        }
+        profile
+    }
+
+    /// Only the given settings of this profile.
+    pub fn subset(&self, fields: &[Field]) -> Self {
+        let mut subset = self.clone();
+        for field in Field::ALL
+            .into_iter()
+            .filter(|field| !fields.contains(field))
+        {
+            field.unset(&mut subset);
+        }
+        subset
+    }
+
+    pub fn fields(&self) -> Vec<Field> {
+        Field::ALL
+            .into_iter()
+            .filter(|field| field.is_set(self))
+            .collect()
+    }
+
+    /// The settings where `settings` no longer agrees with this profile.
+    pub fn drift(&self, settings: &Settings) -> Vec<Field> {
+        Field::ALL
+            .into_iter()
+            .filter(|field| !field.matches(self, settings))
+            .collect()
+    }
+
+    fn summary(&self) -> String {
+        let labels: Vec<_> = self.fields().into_iter().map(Field::label).collect();
+        labels.join(", ")
+    }
+}
+
+/// The profiles file, e.g. `~/.config/mullvad-slint/profiles.json`.
+#[derive(Default, Serialize, Deserialize)]
+struct ProfilesFile {
+    profiles: Vec<Profile>,
+    /// Name of the profile that was applied last.
+    #[serde(default)]
+    active: Option<String>,
+}
+
+struct Store {
+    path: PathBuf,
+    file: ProfilesFile,
+    /// The latest daemon settings, used to tell whether the active profile still applies.
+    settings: Option<Settings>,
+}
+
+impl Store {
+    fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let file = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ProfilesFile::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Store {
+            path,
+            file,
+            settings: None,
+        })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.file)?;
+        preferences::write_atomically(&self.path, &json)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    fn get(&self, name: &str) -> Option<&Profile> {
+        self.file
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+    }
+}
+
+/// Load the profiles, and install callbacks for the profiles view.
+///
+/// `config_home` is the XDG config directory, e.g. `~/.config`.
+pub fn setup(app: &AppWindow, rpc: &Rpc, config_home: &Path) {
+    // If the file can't be loaded, we leave it alone rather than overwriting it with new profiles.
+    let path = config_home.join("mullvad-slint").join("profiles.json");
+    match Store::load(path) {
+        Ok(store) => {
+            *STORE.lock().unwrap() = Some(store);
+            #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+            update_tray(&app.as_weak(), rpc);
+        }
+        Err(e) => {
+            tracing::error!("Failed to load settings profiles: {e:#}");
+            show_result(&app.as_weak(), Err(format!("{e:#}")));
+        }
+    }
+
+    let profiles = app.global::<Profiles>();
+    let fields: VecModel<_> = Field::ALL
+        .iter()
+        .map(|field| slint_ty::ProfileField {
+            label: field.label().into(),
+            selected: false,
+        })
+        .collect();
+    profiles.set_fields(ModelRc::new(fields));
+    update_ui(app);
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        profiles.on_save(move |name| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let name = name.trim().to_owned();
+            let fields: Vec<Field> = app
+                .global::<Profiles>()
+                .get_fields()
+                .iter()
+                .zip(Field::ALL)
+                .filter(|(ui_field, _)| ui_field.selected)
+                .map(|(_, field)| field)
+                .collect();
+
+            if name.is_empty() {
+                return show_result(&app_weak, Err("The profile needs a name.".to_owned()));
+            }
+            if fields.is_empty() {
+                return show_result(&app_weak, Err("Select at least one setting.".to_owned()));
+            }
+
+            #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+            let rpc_handle = rpc.clone();
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let settings = rpc.get_settings().await?;
+                let profile = Profile::capture(name.clone(), &fields, &settings);
+                let result = with_store(|store| {
+                    store.file.profiles.retain(|profile| profile.name != name);
+                    store.file.profiles.push(profile);
+                    store.file.active = Some(name.clone());
+                    store.save()
+                });
+
+                #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+                update_tray(&app_weak, &rpc_handle);
+
+                let status = result
+                    .map(|()| format!("Saved \"{name}\"."))
+                    .map_err(|e| format!("{e:#}"));
+                show_result(&app_weak, status);
+                refresh(&app_weak);
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        profiles.on_switch(move |name| switch(&rpc, &app_weak, name.into()));
+    }
+
+    {
+        #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        profiles.on_remove(move |name| {
+            let result = with_store(|store| {
+                store
+                    .file
+                    .profiles
+                    .retain(|profile| profile.name != name.as_str());
+                if store.file.active.as_deref() == Some(name.as_str()) {
+                    store.file.active = None;
+                }
+                store.save()
+            });
+            #[cfg(all(target_os = "linux", feature = "tray-icon"))]
+            update_tray(&app_weak, &rpc);
+
+            let status = result
+                .map(|()| format!("Removed \"{name}\"."))
+                .map_err(|e| format!("{e:#}"));
+            show_result(&app_weak, status);
+            refresh(&app_weak);
+        });
+    }
+}
+
+/// Apply a profile, and make it the active one.
+fn switch(rpc: &Rpc, app_weak: &Weak<AppWindow>, name: String) {
+    let Some(profile) = with_store(|store| Ok(store.get(&name).cloned()))
+        .ok()
+        .flatten()
+    else {
+        tracing::warn!("No settings profile named \"{name}\"");
+        return;
+    };
+    if let Err(e) = profile.fields().into_iter().try_for_each(enforce::check) {
+        let error = format!("Can't switch to \"{name}\": {e}");
+        return show_result(app_weak, Err(error));
+    }
+
+    let app_weak = app_weak.clone();
+    rpc.spawn_with_rpc(async move |mut rpc| {
+        let result = apply(&mut rpc, &profile).await.and_then(|()| {
+            with_store(|store| {
+                store.file.active = Some(profile.name.clone());
+                store.save()
+            })
+        });
+        let status = match &result {
+            Ok(()) => Ok(format!("Switched to \"{}\".", profile.name)),
+            Err(e) => Err(format!("Failed to switch to \"{}\": {e:#}", profile.name)),
+        };
+        show_result(&app_weak, status);
+        refresh(&app_weak);
+        result
+    });
+}
+
+/// Apply every setting in `profile`, or none of them.
+///
+/// The daemon has no way to change several settings at once, so if one of them fails, the ones
+/// that were already changed are restored.
+pub async fn apply(rpc: &mut MullvadProxyClient, profile: &Profile) -> anyhow::Result<()> {
+    apply_to(rpc, profile).await
+}
+
+/// The daemon calls that [`apply`] makes, so that it can be tested without a daemon.
+trait Daemon {
+    async fn get_settings(&mut self) -> anyhow::Result<Settings>;
+
+    /// Apply every setting in `profile`, in order, stopping at the first that fails.
+    async fn apply_fields(&mut self, profile: &Profile) -> anyhow::Result<()>;
+}
+
+impl Daemon for MullvadProxyClient {
+    async fn get_settings(&mut self) -> anyhow::Result<Settings> {
+        Ok(MullvadProxyClient::get_settings(self).await?)
+    }
+
+    async fn apply_fields(&mut self, profile: &Profile) -> anyhow::Result<()> {
+        apply_fields(self, profile).await
+    }
+}
+
+async fn apply_to(daemon: &mut impl Daemon, profile: &Profile) -> anyhow::Result<()> {
+    let before = daemon.get_settings().await?;
+    let Err(e) = daemon.apply_fields(profile).await else {
+        return Ok(());
+    };
+
+    let restore = Profile::capture(String::new(), &profile.fields(), &before);
+    if let Err(restore_error) = daemon.apply_fields(&restore).await {
+        tracing::error!(
+            "Failed to restore settings after a failed profile switch: {restore_error:#}"
+        );
+    }
+    Err(e)
+}
+
+async fn apply_fields(rpc: &mut MullvadProxyClient, profile: &Profile) -> anyhow::Result<()> {
+    if let Some(allow_lan) = profile.allow_lan {
+        rpc.set_allow_lan(allow_lan).await?;
+    }
+    if let Some(lockdown_mode) = profile.lockdown_mode {
+        rpc.set_lockdown_mode(lockdown_mode).await?;
+    }
+    if let Some(auto_connect) = profile.auto_connect {
+        rpc.set_auto_connect(auto_connect).await?;
+    }
+    if let Some(enable_ipv6) = profile.enable_ipv6 {
+        rpc.set_enable_ipv6(enable_ipv6).await?;
+    }
+    if let Some(daita) = profile.daita {
+        rpc.set_enable_daita(daita).await?;
+    }
+    if let Some(obfuscation) = profile.obfuscation {
+        let mut settings = rpc.get_settings().await?.obfuscation_settings;
+        settings.selected_obfuscation = obfuscation;
+        rpc.set_obfuscation_settings(settings).await?;
+    }
+    if let Some(location) = profile.location.clone() {
+        rpc::update_relay_constraints(rpc, |relay_constraints| {
+            relay_constraints.location = location;
+            Ok(())
+        })
+        .await?;
+    }
+    Ok(())
+}
+
+/// Track the daemon settings, to tell whether the active profile still applies.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let _ = with_store(|store| {
+        store.settings = Some(settings.clone());
+        Ok(())
+    });
+    update_ui(app);
+}
+
+fn location(settings: &Settings) -> Option<&Constraint<LocationConstraint>> {
+    match &settings.relay_settings {
+        RelaySettings::Normal(relay_constraints) => Some(&relay_constraints.location),
+        RelaySettings::CustomTunnelEndpoint(_) => None,
+    }
+}
+
+fn with_store<T>(f: impl FnOnce(&mut Store) -> anyhow::Result<T>) -> anyhow::Result<T> {
+    let mut store = STORE.lock().unwrap();
+    let store = store.as_mut().context("Settings profiles are not loaded")?;
+    f(store)
+}
+
+/// Show the profiles, which one is active, and whether the live settings still match it.
+fn update_ui(app: &AppWindow) {
+    let Ok((list, active, drift)) = with_store(|store| {
+        let list: Vec<_> = store
+            .file
+            .profiles
+            .iter()
+            .map(|profile| slint_ty::Profile {
+                name: profile.name.as_str().into(),
+                summary: profile.summary().into(),
+            })
+            .collect();
+        let active = store
+            .file
+            .active
+            .as_deref()
+            .and_then(|name| store.get(name));
+        let drift = match (active, &store.settings) {
+            (Some(active), Some(settings)) => active.drift(settings),
+            _ => vec![],
+        };
+        let drift: Vec<_> = drift.into_iter().map(Field::label).collect();
+        let active = active
+            .map(|profile| profile.name.clone())
+            .unwrap_or_default();
+        Ok((list, active, drift.join(", ")))
+    }) else {
+        return;
+    };
+
+    let profiles = app.global::<Profiles>();
+    profiles.set_profiles(ModelRc::new(VecModel::from(list)));
+    profiles.set_active(active.into());
+    profiles.set_drift(drift.into());
+}
+
+fn refresh(app_weak: &Weak<AppWindow>) {
+    let _ = app_weak.upgrade_in_event_loop(|app| update_ui(&app));
+}
+
+/// Show the outcome of the last action.
+fn show_result(app_weak: &Weak<AppWindow>, result: Result<String, String>) {
+    let (status, error) = match result {
+        Ok(status) => (status, String::new()),
+        Err(error) => (String::new(), error),
+    };
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let profiles = app.global::<Profiles>();
+        profiles.set_status(status.into());
+        profiles.set_error(error.into());
+    });
+}
+
+/// The IDs of the tray menu items that switch profiles, one per profile in the order they're
+/// saved in.
+///
+/// Menu items can't be removed, so the items are relabelled whenever the profiles change, and
+/// those left over after a profile is removed are blanked. They do nothing when clicked.
+#[cfg(all(target_os = "linux", feature = "tray-icon"))]
+static TRAY_ITEMS: Mutex<Vec<u32>> = Mutex::new(vec![]);
+
+/// Update the tray menu items to the current profiles.
+#[cfg(all(target_os = "linux", feature = "tray-icon"))]
+fn update_tray(app_weak: &Weak<AppWindow>, rpc: &Rpc) {
+    let names: Vec<String> = with_store(|store| {
+        Ok(store
+            .file
+            .profiles
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect())
+    })
+    .unwrap_or_default();
+
+    let mut items = TRAY_ITEMS.lock().unwrap();
+    for (index, name) in names.iter().enumerate() {
+        let label = format!("Profile: {name}");
+        if let Some(&id) = items.get(index) {
+            crate::tray::set_menu_item_label(id, &label);
+            continue;
+        }
+
+        let rpc = rpc.clone();
+        let app_weak = app_weak.clone();
+        // The item switches to whichever profile it shows when it's clicked.
+        let id = crate::tray::add_menu_item(&label, move || {
+            let name = with_store(|store| {
+                Ok(store
+                    .file
+                    .profiles
+                    .get(index)
+                    .map(|profile| profile.name.clone()))
+            });
+            if let Ok(Some(name)) = name {
+                switch(&rpc, &app_weak, name);
+            }
+        });
+        match id {
+            Some(id) => items.push(id),
+            None => break,
+        }
+    }
+    for &id in items.iter().skip(names.len()) {
+        crate::tray::set_menu_item_label(id, "");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A daemon that fails to change lockdown mode once.
+    struct FlakyDaemon {
+        settings: Settings,
+        fail_lockdown_mode: bool,
+    }
+
+    impl Daemon for FlakyDaemon {
+        async fn get_settings(&mut self) -> anyhow::Result<Settings> {
+            Ok(self.settings.clone())
+        }
+
+        async fn apply_fields(&mut self, profile: &Profile) -> anyhow::Result<()> {
+            if let Some(allow_lan) = profile.allow_lan {
+                self.settings.allow_lan = allow_lan;
+            }
+            if let Some(lockdown_mode) = profile.lockdown_mode {
+                if self.fail_lockdown_mode {
+                    self.fail_lockdown_mode = false;
+                    anyhow::bail!("Failed to set lockdown mode");
+                }
+                self.settings.lockdown_mode = lockdown_mode;
+            }
+            Ok(())
+        }
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "Travel".to_owned(),
+            allow_lan: Some(true),
+            lockdown_mode: Some(true),
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn serialize_only_set_fields() {
+        let json = serde_json::to_value(profile()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "Travel",
+                "allow_lan": true,
+                "lockdown_mode": true,
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let profile = Profile {
+            obfuscation: Some(SelectedObfuscation::Shadowsocks),
+            location: Some(Constraint::Only(LocationConstraint::Location(
+                GeographicLocationConstraint::City("se".to_owned(), "got".to_owned()),
+            ))),
+            ..profile()
+        };
+        let json = serde_json::to_string(&profile).unwrap();
+        assert_eq!(serde_json::from_str::<Profile>(&json).unwrap(), profile);
+    }
+
+    #[test]
+    fn missing_fields_are_unset() {
+        let profile: Profile = serde_json::from_str(r#"{ "name": "Home" }"#).unwrap();
+        assert_eq!(profile.fields(), []);
+    }
+
+    #[test]
+    fn apply_all_fields() {
+        let mut daemon = FlakyDaemon {
+            settings: Settings::default(),
+            fail_lockdown_mode: false,
+        };
+        daemon.settings.allow_lan = false;
+        daemon.settings.lockdown_mode = false;
+
+        futures::executor::block_on(apply_to(&mut daemon, &profile())).unwrap();
+        assert!(daemon.settings.allow_lan);
+        assert!(daemon.settings.lockdown_mode);
+    }
+
+    #[test]
+    fn restore_after_partial_failure() {
+        let mut daemon = FlakyDaemon {
+            settings: Settings::default(),
+            fail_lockdown_mode: true,
+        };
+        daemon.settings.allow_lan = false;
+        daemon.settings.lockdown_mode = false;
+
+        let result = futures::executor::block_on(apply_to(&mut daemon, &profile()));
+        assert!(result.is_err());
+        // Local network sharing was changed before lockdown mode failed, and is changed back.
+        assert!(!daemon.settings.allow_lan);
+        assert!(!daemon.settings.lockdown_mode);
+    }
+}
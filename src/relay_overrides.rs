@@ -0,0 +1,183 @@
+//! Server IP overrides: user provided IP addresses for relays.
+
+use std::{
+    collections::HashSet,
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use mullvad_types::{relay_constraints::RelayOverride, settings::Settings};
+use serde::{Deserialize, Serialize};
+use slint::{ComponentHandle as _, Model as _, ModelRc, VecModel};
+
+use crate::{
+    compat::DaemonFeature,
+    rpc::Rpc,
+    slint_ty::{self, AppWindow, RelayList, RelayOverrides},
+};
+
+/// The format of imported overrides. This is a subset of the daemon's JSON settings.
+#[derive(Serialize, Deserialize)]
+struct Import {
+    relay_overrides: Vec<Override>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Override {
+    hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ipv4_addr_in: Option<Ipv4Addr>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ipv6_addr_in: Option<Ipv6Addr>,
+}
+
+/// Install callbacks for the server IP override view.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let rpc = &rpc.for_feature(DaemonFeature::RelayOverrides);
+    let relay_overrides = app.global::<RelayOverrides>();
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        relay_overrides.on_import_file(move |path| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            match fs::read_to_string(path.trim()) {
+                Ok(json) => import(&app, &rpc, &json),
+                Err(e) => {
+                    let relay_overrides = app.global::<RelayOverrides>();
+                    relay_overrides.set_status("".into());
+                    relay_overrides.set_error(format!("Failed to read {path}: {e}").into());
+                }
+            }
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        relay_overrides.on_import_text(move |json| {
+            if let Some(app) = app_weak.upgrade() {
+                import(&app, &rpc, &json);
+            }
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        relay_overrides.on_clear(move |hostname| {
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                // An override without addresses is removed.
+                rpc.set_relay_override(RelayOverride::empty(hostname.into()))
+                    .await?;
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        relay_overrides.on_clear_all(move || {
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                rpc.clear_all_relay_overrides().await?;
+                Ok(())
+            });
+        });
+    }
+}
+
+/// Sync the server IP override view and the relay list markers with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let mut overrides: Vec<_> = settings
+        .relay_overrides
+        .iter()
+        .map(|relay_override| slint_ty::RelayOverride {
+            hostname: relay_override.hostname.as_str().into(),
+            ipv4: relay_override
+                .ipv4_addr_in
+                .map(|ip| ip.to_string())
+                .unwrap_or_default()
+                .into(),
+            ipv6: relay_override
+                .ipv6_addr_in
+                .map(|ip| ip.to_string())
+                .unwrap_or_default()
+                .into(),
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+
+    app.global::<RelayOverrides>()
+        .set_overrides(ModelRc::new(VecModel::from(overrides)));
+    mark_relays(app);
+}
+
+/// Mark the relays in the relay list that have an override.
+pub fn mark_relays(app: &AppWindow) {
+    let overridden: HashSet<_> = app
+        .global::<RelayOverrides>()
+        .get_overrides()
+        .iter()
+        .map(|relay_override| relay_override.hostname)
+        .collect();
+
+    // Filtered countries share their city and relay models with these.
+    let countries = app.global::<RelayList>().get_countries();
+    for country in countries.iter() {
+        for city in country.cities.iter() {
+            let relays = &city.relays;
+            for (row, mut relay) in relays.iter().enumerate() {
+                let has_override = overridden.contains(&relay.hostname);
+                if relay.has_override != has_override {
+                    relay.has_override = has_override;
+                    relays.set_row_data(row, relay);
+                }
+            }
+        }
+    }
+}
+
+/// Parse `json` and send the overrides to the daemon. Problems are shown in the UI.
+fn import(app: &AppWindow, rpc: &Rpc, json: &str) {
+    let relay_overrides = app.global::<RelayOverrides>();
+    relay_overrides.set_status("".into());
+
+    let parsed = match serde_json::from_str::<Import>(json) {
+        Ok(parsed) if parsed.relay_overrides.is_empty() => {
+            relay_overrides.set_error("No overrides found.".into());
+            return;
+        }
+        Ok(parsed) => parsed,
+        Err(e) => {
+            relay_overrides.set_error(format!("Failed to parse overrides: {e}").into());
+            return;
+        }
+    };
+    relay_overrides.set_error("".into());
+
+    // Only pass on the overrides, not any other settings that the input may contain.
+    let count = parsed.relay_overrides.len();
+    let json = serde_json::to_string(&parsed).expect("Overrides can be serialized");
+
+    let app_weak = app.as_weak();
+    rpc.spawn_with_rpc(async move |mut rpc| {
+        let result = rpc.apply_json_settings(json).await;
+        let (status, error) = match &result {
+            Ok(()) if count == 1 => ("Imported 1 override.".to_owned(), String::new()),
+            Ok(()) => (format!("Imported {count} overrides."), String::new()),
+            Err(e) => (
+                String::new(),
+                format!("The daemon rejected the overrides: {e}"),
+            ),
+        };
+        let _ = app_weak.upgrade_in_event_loop(move |app| {
+            let relay_overrides = app.global::<RelayOverrides>();
+            relay_overrides.set_status(status.into());
+            relay_overrides.set_error(error.into());
+        });
+
+        result?;
+        Ok(())
+    });
+}
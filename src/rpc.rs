@@ -1,22 +1,54 @@
-use crate::RT;
+use crate::{
+    RT,
+    compat::{self, Compatibility, DaemonFeature, DaemonInfo},
+};
 use std::{sync::Arc, time::Duration};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
 use mullvad_management_interface::MullvadProxyClient;
-use tokio::{sync::Mutex, time::sleep};
+use mullvad_types::relay_constraints::{RelayConstraints, RelaySettings};
+use tokio::{
+    sync::{Mutex, watch},
+    time::sleep,
+};
 
 #[derive(Clone)]
 pub struct Rpc {
     rpc: Arc<Mutex<Option<MullvadProxyClient>>>,
+    /// The daemon we last connected to. `None` until the first handshake.
+    daemon: Arc<watch::Sender<Option<DaemonInfo>>>,
+    /// The feature that calls made through this handle belong to, see [`Self::for_feature`].
+    feature: Option<DaemonFeature>,
 }
 
 impl Rpc {
     pub fn new() -> Self {
         Self {
             rpc: Arc::new(Mutex::new(None)),
+            daemon: Arc::new(watch::Sender::new(None)),
+            feature: None,
         }
     }
 
+    /// A handle whose calls belong to `feature`. If the daemon rejects one of them as
+    /// unimplemented, the feature is marked as unsupported.
+    pub fn for_feature(&self, feature: DaemonFeature) -> Self {
+        Self {
+            feature: Some(feature),
+            ..self.clone()
+        }
+    }
+
+    /// Watch the daemon we're connected to. Updated on every (re)connect.
+    pub fn subscribe_daemon(&self) -> watch::Receiver<Option<DaemonInfo>> {
+        self.daemon.subscribe()
+    }
+
+    /// Drop the gRPC connection, so that the next call reconnects and redoes the handshake.
+    async fn disconnect(&self) {
+        self.rpc.lock().await.take();
+    }
+
     /// Try to execute a function with a gRPC connection.
     ///
     /// # Errors
@@ -31,9 +63,11 @@ impl Rpc {
 
             // Connect to gRPC if not already connected
             if rpc_option.is_none() {
-                let rpc = MullvadProxyClient::new()
+                let mut rpc = MullvadProxyClient::new()
                     .await
                     .context("Failed to open RPC connection")?;
+                let daemon = handshake(&mut rpc).await?;
+                self.daemon.send_replace(Some(daemon));
                 *rpc_option = Some(rpc);
             };
 
@@ -59,7 +93,28 @@ impl Rpc {
         RT.spawn(async move {
             let result = this.with_rpc(f).await;
             if let Err(e) = result {
-                tracing::error!("{e:#?}");
+                this.report_error(&e);
+            }
+        });
+    }
+
+    /// Log a failed call. Calls the daemon is too old for are also shown in the UI, and disable
+    /// the feature they belong to.
+    fn report_error(&self, e: &anyhow::Error) {
+        if !compat::is_unsupported(e) {
+            tracing::error!("{e:#?}");
+            return;
+        }
+
+        tracing::warn!("The daemon does not support this call: {e:#}");
+        self.daemon.send_modify(|daemon| {
+            if let Some(daemon) = daemon {
+                daemon.rejected_calls = true;
+                if let Some(feature) = self.feature
+                    && !daemon.unsupported.contains(&feature)
+                {
+                    daemon.unsupported.push(feature);
+                }
             }
         });
     }
@@ -82,6 +137,8 @@ impl Rpc {
                 if let Err(e) = result {
                     tracing::error!("{e:#?}");
                     tracing::error!("Retrying in 1 sec.");
+                    // The daemon may have been restarted, possibly as a different version.
+                    this.disconnect().await;
                     sleep(Duration::from_secs(1)).await;
                     continue;
                 }
@@ -89,4 +146,54 @@ impl Rpc {
             }
         });
     }
+
+    /// Shorthand for spawning [`update_relay_constraints`].
+    pub fn update_relay_constraints<F>(&self, f: F)
+    where
+        F: FnOnce(&mut RelayConstraints) -> anyhow::Result<()> + Send + 'static,
+    {
+        self.spawn_with_rpc(async move |mut rpc| update_relay_constraints(&mut rpc, f).await);
+    }
+}
+
+/// Ask the daemon for its version, and check that we speak the same protocol.
+///
+/// A mismatch is not an error: we keep talking to the daemon, and let the UI explain what may break.
+async fn handshake(rpc: &mut MullvadProxyClient) -> anyhow::Result<DaemonInfo> {
+    let version = rpc
+        .get_current_version()
+        .await
+        .context("Failed to query daemon version")?;
+    let daemon = DaemonInfo::new(version);
+
+    match daemon.compatibility {
+        Compatibility::Compatible => {
+            tracing::info!("Connected to daemon version {}", daemon.version);
+        }
+        compatibility => tracing::warn!(
+            "Daemon version {} is incompatible with this app, which was built for {} ({compatibility:?})",
+            daemon.version,
+            compat::INTERFACE_VERSION,
+        ),
+    }
+
+    Ok(daemon)
+}
+
+/// Fetch the current relay constraints, apply `f`, and send them back to the daemon.
+///
+/// # Errors
+/// Returns `Err` if the daemon is configured to use a custom relay, or if `f` returns `Err`.
+pub async fn update_relay_constraints<F>(rpc: &mut MullvadProxyClient, f: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut RelayConstraints) -> anyhow::Result<()>,
+{
+    let relay_settings = rpc.get_settings().await?.relay_settings;
+    let RelaySettings::Normal(mut relay_constraints) = relay_settings else {
+        bail!("A custom server is in use. Switch back to the Mullvad relays first");
+    };
+    f(&mut relay_constraints)?;
+    rpc.set_relay_settings(RelaySettings::Normal(relay_constraints))
+        .await?;
+    Ok(())
 }
@@ -0,0 +1,479 @@
+//! Export the daemon settings to a file, and import settings patches through the daemon's JSON
+//! settings API.
+//!
+//! The daemon only lets a few settings be patched, see [`PERMITTED`]. Only those are exported, so
+//! that an exported file can be imported on another machine as it is.
+
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    path::Path,
+};
+
+use anyhow::Context as _;
+use serde_json::{Map, Value};
+use slint::{ComponentHandle as _, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    compat::DaemonFeature,
+    rpc::Rpc,
+    slint_ty::{AppWindow, SettingsTransfer},
+};
+
+/// The parts of the settings that the daemon accepts in a patch.
+enum Permitted {
+    /// Any value.
+    Any,
+    /// An object with only these keys.
+    Object(&'static [(&'static str, Permitted)]),
+    /// An array where every element is permitted.
+    Array(&'static Permitted),
+}
+
+/// Mirrors the daemon's list of patchable settings, so that we can point out what it would reject.
+const PERMITTED: Permitted = Permitted::Object(&[(
+    "relay_overrides",
+    Permitted::Array(&Permitted::Object(&[
+        ("hostname", Permitted::Any),
+        ("ipv4_addr_in", Permitted::Any),
+        ("ipv6_addr_in", Permitted::Any),
+    ])),
+)]);
+
+/// Install callbacks for the settings import and export view.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let rpc = &rpc.for_feature(DaemonFeature::SettingsImport);
+    let settings_transfer = app.global::<SettingsTransfer>();
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        settings_transfer.on_export(move |path| {
+            let path = path.trim().to_owned();
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let settings = rpc.get_settings().await?;
+                let result = serde_json::to_value(&settings)
+                    .map(|settings| importable(&settings, &PERMITTED).unwrap_or_default())
+                    .and_then(|settings| serde_json::to_string_pretty(&settings))
+                    .context("Failed to serialize settings")
+                    .and_then(|json| {
+                        write_private(Path::new(&path), &(json + "\n"))
+                            .with_context(|| format!("Failed to write {path}"))
+                    });
+                let result = result
+                    .map(|()| format!("Exported the settings that can be imported to {path}."))
+                    .map_err(|e| format!("{e:#}"));
+                show_result(&app_weak, result);
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let app_weak = app.as_weak();
+        settings_transfer.on_load_file(move |path| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let settings_transfer = app.global::<SettingsTransfer>();
+            match fs::read_to_string(path.trim()) {
+                Ok(patch) => {
+                    settings_transfer.set_patch(patch.into());
+                    settings_transfer.invoke_preview();
+                }
+                Err(e) => show_result(&app_weak, Err(format!("Failed to read {path}: {e}"))),
+            }
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        settings_transfer.on_preview(move || {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let settings_transfer = app.global::<SettingsTransfer>();
+            settings_transfer.set_previewed(false);
+            settings_transfer.set_changes(ModelRc::default());
+
+            let patch = match parse_patch(&settings_transfer.get_patch()) {
+                Ok(patch) => patch,
+                Err(error) => return show_result(&app_weak, Err(error)),
+            };
+            show_result(&app_weak, Ok(String::new()));
+
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let settings = rpc.get_settings().await?;
+                let current =
+                    serde_json::to_value(&settings).context("Failed to serialize settings")?;
+                let mut patched = current.clone();
+                merge(&mut patched, &patch);
+
+                let mut changes = vec![];
+                diff("", &current, &patched, &mut changes);
+
+                let _ = app_weak.upgrade_in_event_loop(move |app| {
+                    let settings_transfer = app.global::<SettingsTransfer>();
+                    if changes.is_empty() {
+                        let status = "The patch would not change anything.";
+                        settings_transfer.set_status(status.into());
+                    }
+                    let changes: VecModel<SharedString> =
+                        changes.into_iter().map(SharedString::from).collect();
+                    settings_transfer.set_changes(ModelRc::new(changes));
+                    settings_transfer.set_previewed(true);
+                });
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        settings_transfer.on_apply(move || {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            let patch = match parse_patch(&app.global::<SettingsTransfer>().get_patch()) {
+                Ok(patch) => patch.to_string(),
+                Err(error) => return show_result(&app_weak, Err(error)),
+            };
+
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                let result = rpc.apply_json_settings(patch).await;
+                let status = match &result {
+                    Ok(()) => Ok("The settings were imported.".to_owned()),
+                    Err(e) => Err(format!("The daemon rejected the patch: {e}")),
+                };
+                show_result(&app_weak, status);
+                let _ = app_weak.upgrade_in_event_loop(|app| {
+                    let settings_transfer = app.global::<SettingsTransfer>();
+                    settings_transfer.set_previewed(false);
+                    settings_transfer.set_changes(ModelRc::default());
+                });
+
+                result?;
+                Ok(())
+            });
+        });
+    }
+}
+
+/// Write `contents` to `path`, only readable by the user.
+pub fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to new files.
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+/// Parse a settings patch, and check that it only touches settings the daemon lets us patch.
+fn parse_patch(patch: &str) -> Result<Value, String> {
+    let patch: Value =
+        serde_json::from_str(patch).map_err(|e| format!("Failed to parse the patch: {e}"))?;
+
+    let mut rejected = vec![];
+    check_permitted("", &patch, &PERMITTED, &mut rejected);
+    if !rejected.is_empty() {
+        return Err(format!(
+            "The patch contains settings that can't be imported: {}",
+            rejected.join(", ")
+        ));
+    }
+
+    match patch.as_object() {
+        Some(object) if !object.is_empty() => Ok(patch),
+        _ => Err("The patch is empty.".to_owned()),
+    }
+}
+
+/// Collect the paths in `value` that aren't `permitted`.
+fn check_permitted(path: &str, value: &Value, permitted: &Permitted, rejected: &mut Vec<String>) {
+    match (permitted, value) {
+        (Permitted::Any, _) => {}
+        (Permitted::Object(keys), Value::Object(object)) => {
+            for (key, value) in object {
+                let key_path = join(path, key);
+                match keys.iter().find(|(permitted_key, _)| permitted_key == key) {
+                    Some((_, permitted)) => check_permitted(&key_path, value, permitted, rejected),
+                    None => rejected.push(key_path),
+                }
+            }
+        }
+        (Permitted::Array(permitted), Value::Array(array)) => {
+            for (i, value) in array.iter().enumerate() {
+                check_permitted(&format!("{path}[{i}]"), value, permitted, rejected);
+            }
+        }
+        _ => rejected.push(
+            if path.is_empty() {
+                "the patch itself"
+            } else {
+                path
+            }
+            .to_owned(),
+        ),
+    }
+}
+
+/// The parts of `value` that are `permitted`, or `None` if there are none.
+fn importable(value: &Value, permitted: &Permitted) -> Option<Value> {
+    match (permitted, value) {
+        (Permitted::Any, value) => Some(value.clone()),
+        (Permitted::Object(keys), Value::Object(object)) => {
+            let object: Map<String, Value> = keys
+                .iter()
+                .filter_map(|(key, permitted)| {
+                    Some((key.to_string(), importable(object.get(*key)?, permitted)?))
+                })
+                .collect();
+            Some(Value::Object(object))
+        }
+        (Permitted::Array(permitted), Value::Array(array)) => Some(Value::Array(
+            array
+                .iter()
+                .filter_map(|value| importable(value, permitted))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Apply `patch` to `settings` the way the daemon does.
+///
+/// Objects are merged key by key. Relay overrides are merged by hostname, so that an override
+/// only replaces the addresses it sets.
+fn merge(settings: &mut Value, patch: &Value) {
+    let (Value::Object(settings), Value::Object(patch)) = (settings, patch) else {
+        return;
+    };
+
+    for (key, value) in patch {
+        match (key.as_str(), settings.get_mut(key)) {
+            ("relay_overrides", Some(Value::Array(current))) => {
+                for new in value.as_array().into_iter().flatten() {
+                    let existing = current
+                        .iter_mut()
+                        .find(|current| current.get("hostname") == new.get("hostname"));
+                    match (existing, new) {
+                        (Some(Value::Object(existing)), Value::Object(new)) => {
+                            existing.extend(new.clone());
+                        }
+                        _ => current.push(new.clone()),
+                    }
+                }
+            }
+            (_, Some(current @ Value::Object(_))) => merge(current, value),
+            _ => {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Describe the differences between `old` and `new`, one line per changed value.
+fn diff(path: &str, old: &Value, new: &Value, changes: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                match new.get(key) {
+                    Some(new_value) => diff(&join(path, key), old_value, new_value, changes),
+                    None => changes.push(format!("- {}: {old_value}", join(path, key))),
+                }
+            }
+            for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                changes.push(format!("+ {}: {new_value}", join(path, key)));
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            let old = keyed(old);
+            let new = keyed(new);
+            diff(path, &Value::Object(old), &Value::Object(new), changes);
+        }
+        (old, new) if old != new => changes.push(format!("~ {path}: {old} → {new}")),
+        _ => {}
+    }
+}
+
+/// Key array elements by their hostname if they have one, otherwise by index.
+fn keyed(array: &[Value]) -> Map<String, Value> {
+    array
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let key = match value.get("hostname").and_then(Value::as_str) {
+                Some(hostname) => format!("[{hostname}]"),
+                None => format!("[{i}]"),
+            };
+            (key, value.clone())
+        })
+        .collect()
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_owned(),
+        path if key.starts_with('[') => format!("{path}{key}"),
+        path => format!("{path}.{key}"),
+    }
+}
+
+/// Show the outcome of the last action. An empty status clears the previous one.
+fn show_result(app_weak: &Weak<AppWindow>, result: Result<String, String>) {
+    let (status, error) = match result {
+        Ok(status) => (status, String::new()),
+        Err(error) => (String::new(), error),
+    };
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let settings_transfer = app.global::<SettingsTransfer>();
+        settings_transfer.set_status(status.into());
+        settings_transfer.set_error(error.into());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn relay_override(hostname: &str, ipv4: &str) -> Value {
+        json!({ "hostname": hostname, "ipv4_addr_in": ipv4, "ipv6_addr_in": null })
+    }
+
+    #[test]
+    fn export_only_importable_settings() {
+        let settings = json!({
+            "allow_lan": true,
+            "relay_overrides": [relay_override("se-got-wg-001", "10.0.0.1")],
+            "tunnel_options": { "generic": { "enable_ipv6": false } },
+        });
+
+        let exported = importable(&settings, &PERMITTED).unwrap();
+        assert_eq!(
+            exported,
+            json!({ "relay_overrides": [relay_override("se-got-wg-001", "10.0.0.1")] })
+        );
+        // What's exported can be imported again.
+        assert_eq!(parse_patch(&exported.to_string()), Ok(exported));
+    }
+
+    #[test]
+    fn reject_unpermitted_settings() {
+        let mut rejected = vec![];
+        let patch = json!({
+            "allow_lan": true,
+            "relay_overrides": [
+                relay_override("se-got-wg-001", "10.0.0.1"),
+                { "hostname": "se-got-wg-002", "port": 53 },
+            ],
+        });
+        check_permitted("", &patch, &PERMITTED, &mut rejected);
+        assert_eq!(rejected, ["allow_lan", "relay_overrides[1].port"]);
+
+        let mut rejected = vec![];
+        check_permitted("", &json!([]), &PERMITTED, &mut rejected);
+        assert_eq!(rejected, ["the patch itself"]);
+    }
+
+    #[test]
+    fn parse_patches() {
+        assert!(parse_patch("{").is_err());
+        assert_eq!(parse_patch("{}"), Err("The patch is empty.".to_owned()));
+        assert!(parse_patch(r#"{ "allow_lan": true }"#).is_err());
+        assert!(parse_patch(r#"{ "relay_overrides": [] }"#).is_ok());
+    }
+
+    #[test]
+    fn merge_relay_overrides_by_hostname() {
+        let mut settings = json!({
+            "allow_lan": false,
+            "relay_overrides": [relay_override("se-got-wg-001", "10.0.0.1")],
+        });
+        let patch = json!({
+            "relay_overrides": [
+                { "hostname": "se-got-wg-001", "ipv6_addr_in": "fd00::1" },
+                relay_override("se-got-wg-002", "10.0.0.2"),
+            ],
+        });
+
+        merge(&mut settings, &patch);
+        assert_eq!(
+            settings,
+            json!({
+                "allow_lan": false,
+                "relay_overrides": [
+                    {
+                        "hostname": "se-got-wg-001",
+                        "ipv4_addr_in": "10.0.0.1",
+                        "ipv6_addr_in": "fd00::1",
+                    },
+                    relay_override("se-got-wg-002", "10.0.0.2"),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn merge_objects_by_key() {
+        let mut settings = json!({ "a": { "b": 1, "c": 2 }, "d": [1] });
+        merge(
+            &mut settings,
+            &json!({ "a": { "c": 3 }, "d": [2], "e": null }),
+        );
+        assert_eq!(
+            settings,
+            json!({ "a": { "b": 1, "c": 3 }, "d": [2], "e": null })
+        );
+    }
+
+    #[test]
+    fn describe_changes() {
+        let old = json!({
+            "allow_lan": false,
+            "removed": 1,
+            "relay_overrides": [relay_override("se-got-wg-001", "10.0.0.1")],
+        });
+        let new = json!({
+            "allow_lan": true,
+            "added": 2,
+            "relay_overrides": [relay_override("se-got-wg-001", "10.0.0.9")],
+        });
+
+        let mut changes = vec![];
+        diff("", &old, &new, &mut changes);
+        changes.sort();
+        assert_eq!(
+            changes,
+            [
+                "+ added: 2",
+                "- removed: 1",
+                r#"~ allow_lan: false → true"#,
+                r#"~ relay_overrides[se-got-wg-001].ipv4_addr_in: "10.0.0.1" → "10.0.0.9""#,
+            ]
+        );
+
+        let mut changes = vec![];
+        diff("", &old, &old, &mut changes);
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn key_by_hostname_or_index() {
+        let keyed = keyed(&[relay_override("se-got-wg-001", "10.0.0.1"), json!(5)]);
+        let keys: Vec<&str> = keyed.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["[1]", "[se-got-wg-001]"]);
+    }
+}
@@ -1,24 +1,195 @@
 //! Generated types from .slint-files
 
-use mullvad_types::constraints::Constraint;
-use talpid_types::net::IpVersion;
+use mullvad_types::{
+    constraints::Constraint, features::FeatureIndicator, states::TunnelState,
+    wireguard::QuantumResistantState,
+};
+use slint::ToSharedString as _;
+use talpid_types::{
+    net::{IpVersion, obfuscation::ObfuscationType},
+    tunnel::{ActionAfterDisconnect, ErrorState, ErrorStateCause, ParameterGenerationError},
+};
+
+use crate::compat::{Compatibility, DaemonFeature};
 
 slint::include_modules!();
 
 impl Eq for Relay {}
 
-impl From<&mullvad_types::states::TunnelState> for ConnectionState {
-    fn from(tunnel_state: &mullvad_types::states::TunnelState) -> Self {
+impl From<&TunnelState> for ConnectionState {
+    fn from(tunnel_state: &TunnelState) -> Self {
         match tunnel_state {
-            mullvad_types::states::TunnelState::Disconnected { .. } => {
-                ConnectionState::Disconnected
-            }
-            mullvad_types::states::TunnelState::Connecting { .. } => ConnectionState::Connecting,
-            mullvad_types::states::TunnelState::Connected { .. } => ConnectionState::Connected,
-            mullvad_types::states::TunnelState::Disconnecting { .. } => {
+            TunnelState::Disconnected {
+                locked_down: true, ..
+            } => ConnectionState::Blocked,
+            TunnelState::Disconnected { .. } => ConnectionState::Disconnected,
+            TunnelState::Connecting { .. } => ConnectionState::Connecting,
+            TunnelState::Connected { .. } => ConnectionState::Connected,
+            TunnelState::Disconnecting(ActionAfterDisconnect::Nothing) => {
                 ConnectionState::Disconnecting
             }
-            mullvad_types::states::TunnelState::Error { .. } => ConnectionState::Error,
+            TunnelState::Disconnecting(ActionAfterDisconnect::Block) => ConnectionState::Blocked,
+            TunnelState::Disconnecting(ActionAfterDisconnect::Reconnect) => {
+                ConnectionState::Reconnecting
+            }
+            TunnelState::Error { .. } => ConnectionState::Error,
+        }
+    }
+}
+
+impl From<&TunnelState> for ConnectionDetails {
+    fn from(tunnel_state: &TunnelState) -> Self {
+        let (TunnelState::Connecting {
+            endpoint, location, ..
+        }
+        | TunnelState::Connected {
+            endpoint, location, ..
+        }) = tunnel_state
+        else {
+            return ConnectionDetails::default();
+        };
+
+        // The endpoint we're actually sending packets to.
+        let first_hop = endpoint
+            .obfuscation
+            .as_ref()
+            .map(|obfuscation| &obfuscation.endpoint)
+            .or(endpoint.entry_endpoint.as_ref())
+            .unwrap_or(&endpoint.endpoint);
+
+        let obfuscation = endpoint.obfuscation.as_ref().map(|obfuscation| {
+            let name = match obfuscation.obfuscation_type {
+                ObfuscationType::Udp2Tcp => "UDP-over-TCP",
+                ObfuscationType::Shadowsocks => "Shadowsocks",
+                ObfuscationType::Quic => "QUIC",
+                _ => "Unknown",
+            };
+            format!("{name} ({})", obfuscation.endpoint.address)
+        });
+
+        let location = location.as_ref();
+        let entry_relay = location
+            .and_then(|l| l.entry_hostname.as_deref())
+            .filter(|_| endpoint.entry_endpoint.is_some());
+        let exit_relay = location.and_then(|l| l.hostname.as_deref());
+
+        ConnectionDetails {
+            endpoint: first_hop.address.to_shared_string(),
+            protocol: format!("WireGuard {}", first_hop.protocol).into(),
+            entry_relay: entry_relay.unwrap_or_default().into(),
+            exit_relay: exit_relay.unwrap_or_default().into(),
+            obfuscation: obfuscation.unwrap_or_default().into(),
+            ipv4: location
+                .and_then(|l| l.ipv4)
+                .map(|ip| ip.to_shared_string())
+                .unwrap_or_default(),
+            ipv6: location
+                .and_then(|l| l.ipv6)
+                .map(|ip| ip.to_shared_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Features that are active for the current tunnel, in the order they should be shown.
+pub fn active_features(tunnel_state: &TunnelState) -> Vec<Feature> {
+    let (TunnelState::Connecting {
+        endpoint,
+        feature_indicators,
+        ..
+    }
+    | TunnelState::Connected {
+        endpoint,
+        feature_indicators,
+        ..
+    }) = tunnel_state
+    else {
+        return vec![];
+    };
+
+    let mut features: Vec<Feature> = feature_indicators
+        .active_features()
+        .filter_map(|indicator| {
+            Some(match indicator {
+                FeatureIndicator::Daita => Feature::Daita,
+                FeatureIndicator::DaitaMultihop => Feature::DaitaMultihop,
+                FeatureIndicator::QuantumResistance => Feature::QuantumResistance,
+                FeatureIndicator::Multihop => Feature::Multihop,
+                FeatureIndicator::Udp2Tcp => Feature::Udp2tcp,
+                FeatureIndicator::Shadowsocks => Feature::Shadowsocks,
+                FeatureIndicator::Quic => Feature::Quic,
+                FeatureIndicator::SplitTunneling => Feature::SplitTunneling,
+                FeatureIndicator::LockdownMode => Feature::LockdownMode,
+                FeatureIndicator::LanSharing => Feature::LanSharing,
+                FeatureIndicator::DnsContentBlockers => Feature::DnsContentBlockers,
+                FeatureIndicator::CustomDns => Feature::CustomDns,
+                FeatureIndicator::ServerIpOverride => Feature::ServerIpOverride,
+                FeatureIndicator::CustomMtu => Feature::CustomMtu,
+                // Not something this GUI knows how to show (yet).
+                _ => return None,
+            })
+        })
+        .collect();
+
+    // Make sure a post-quantum tunnel is always indicated, even if the daemon left it out.
+    if endpoint.quantum_resistant && !features.contains(&Feature::QuantumResistance) {
+        features.push(Feature::QuantumResistance);
+    }
+
+    features.sort_by_key(|&feature| feature as u32);
+    features
+}
+
+impl From<&ErrorState> for ErrorInfo {
+    fn from(error_state: &ErrorState) -> Self {
+        let (message, action) = match error_state.cause() {
+            ErrorStateCause::AuthFailed(_) => (
+                "Unable to authenticate your account. Please check that it has time left."
+                    .to_owned(),
+                ErrorAction::Disconnect,
+            ),
+            ErrorStateCause::Ipv6Unavailable => (
+                "Could not configure IPv6. Disable it in the app or enable it on your device."
+                    .to_owned(),
+                ErrorAction::OpenVpnSettings,
+            ),
+            ErrorStateCause::SetFirewallPolicyError(_) => (
+                "Failed to apply firewall rules. The device might currently be unsecured."
+                    .to_owned(),
+                ErrorAction::Disconnect,
+            ),
+            ErrorStateCause::SetDnsError => (
+                "Failed to set system DNS server.".to_owned(),
+                ErrorAction::Disconnect,
+            ),
+            ErrorStateCause::IsOffline => (
+                "Your device is offline. The tunnel will automatically connect once your device is back online."
+                    .to_owned(),
+                ErrorAction::None,
+            ),
+            ErrorStateCause::TunnelParameterError(ParameterGenerationError::NoMatchingRelay) => (
+                "No servers match your settings, try changing server or other settings."
+                    .to_owned(),
+                ErrorAction::SelectLocation,
+            ),
+            ErrorStateCause::TunnelParameterError(ParameterGenerationError::NoWireguardKey) => (
+                "Valid WireGuard key is missing. Try logging out and back in again.".to_owned(),
+                ErrorAction::Disconnect,
+            ),
+            ErrorStateCause::TunnelParameterError(
+                ParameterGenerationError::IpVersionUnavailable { .. },
+            ) => (
+                "The selected device IP version is not available on this network.".to_owned(),
+                ErrorAction::OpenVpnSettings,
+            ),
+            // Fall back on the daemon's own description for anything else.
+            cause => (cause.to_string(), ErrorAction::Disconnect),
+        };
+
+        ErrorInfo {
+            message: message.into(),
+            blocking: error_state.is_blocking(),
+            action,
         }
     }
 }
@@ -42,3 +213,46 @@ impl From<DeviceIpVersion> for Constraint<IpVersion> {
         }
     }
 }
+
+impl From<QuantumResistantState> for QuantumResistance {
+    fn from(state: QuantumResistantState) -> Self {
+        match state {
+            QuantumResistantState::Auto => QuantumResistance::Auto,
+            QuantumResistantState::On => QuantumResistance::On,
+            QuantumResistantState::Off => QuantumResistance::Off,
+        }
+    }
+}
+
+impl From<QuantumResistance> for QuantumResistantState {
+    fn from(quantum_resistance: QuantumResistance) -> Self {
+        match quantum_resistance {
+            QuantumResistance::Auto => QuantumResistantState::Auto,
+            QuantumResistance::On => QuantumResistantState::On,
+            QuantumResistance::Off => QuantumResistantState::Off,
+        }
+    }
+}
+
+impl From<&[DaemonFeature]> for UnsupportedFeatures {
+    fn from(unsupported: &[DaemonFeature]) -> Self {
+        UnsupportedFeatures {
+            daita: unsupported.contains(&DaemonFeature::Daita),
+            obfuscation: unsupported.contains(&DaemonFeature::Obfuscation),
+            api_access: unsupported.contains(&DaemonFeature::ApiAccess),
+            relay_overrides: unsupported.contains(&DaemonFeature::RelayOverrides),
+            settings_import: unsupported.contains(&DaemonFeature::SettingsImport),
+        }
+    }
+}
+
+impl From<Compatibility> for DaemonCompatibility {
+    fn from(compatibility: Compatibility) -> Self {
+        match compatibility {
+            Compatibility::Compatible => DaemonCompatibility::Compatible,
+            Compatibility::DaemonOlder => DaemonCompatibility::DaemonOlder,
+            Compatibility::DaemonNewer => DaemonCompatibility::DaemonNewer,
+            Compatibility::Unknown => DaemonCompatibility::Unknown,
+        }
+    }
+}
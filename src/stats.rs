@@ -0,0 +1,214 @@
+//! Session statistics for the connected tunnel.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use slint::{ComponentHandle as _, Weak};
+use tokio::{task::AbortHandle, time::interval_at};
+
+use crate::{
+    RT,
+    slint_ty::{AppWindow, SessionStats, State},
+};
+
+/// Name of the tunnel interface, if the daemon doesn't tell us.
+pub const DEFAULT_INTERFACE: &str = "wg0-mullvad";
+
+/// How often to sample the interface counters.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Byte counters of a network interface.
+#[derive(Clone, Copy, Debug, Default)]
+struct Counters {
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+impl Counters {
+    /// Read the counters of `interface` from `<sysfs_root>/class/net/<interface>/statistics`.
+    fn read(sysfs_root: &Path, interface: &str) -> io::Result<Self> {
+        let dir = sysfs_root
+            .join("class/net")
+            .join(interface)
+            .join("statistics");
+        let read = |name: &str| -> io::Result<u64> {
+            fs::read_to_string(dir.join(name))?
+                .trim()
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+
+        Ok(Counters {
+            rx_bytes: read("rx_bytes")?,
+            tx_bytes: read("tx_bytes")?,
+        })
+    }
+}
+
+/// A running tunnel session. Statistics are published to the UI until this is dropped.
+pub struct Session {
+    task: AbortHandle,
+    app_weak: Weak<AppWindow>,
+}
+
+impl Session {
+    /// Start a new session, counting from now.
+    pub fn start(sysfs_root: PathBuf, interface: String, app_weak: Weak<AppWindow>) -> Self {
+        let started = Instant::now();
+        let task = RT.spawn(sample_loop(
+            sysfs_root,
+            interface,
+            started,
+            app_weak.clone(),
+        ));
+
+        Session {
+            task: task.abort_handle(),
+            app_weak,
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = self.app_weak.upgrade_in_event_loop(|app| {
+            app.global::<State>().set_session(SessionStats::default());
+        });
+    }
+}
+
+async fn sample_loop(
+    sysfs_root: PathBuf,
+    interface: String,
+    started: Instant,
+    app_weak: Weak<AppWindow>,
+) {
+    // Counters are reported relative to the start of the session.
+    let baseline = Counters::read(&sysfs_root, &interface)
+        .inspect_err(|e| tracing::warn!("Failed to read statistics for {interface}: {e}"))
+        .unwrap_or_default();
+    let mut previous = (started, baseline);
+
+    // The first sample is taken one interval after the baseline, so that the first rate isn't
+    // measured over a few microseconds.
+    let mut interval = interval_at(
+        tokio::time::Instant::from_std(started) + SAMPLE_INTERVAL,
+        SAMPLE_INTERVAL,
+    );
+    loop {
+        interval.tick().await;
+
+        let now = Instant::now();
+        let counters = Counters::read(&sysfs_root, &interface).ok();
+
+        let mut stats = SessionStats {
+            duration: format_duration(now - started).into(),
+            ..Default::default()
+        };
+
+        if let Some(counters) = counters {
+            let (previous_time, previous_counters) = previous;
+            let elapsed = (now - previous_time).as_secs_f64().max(f64::EPSILON);
+            let rate = |bytes: u64, previous_bytes: u64| {
+                bytes.saturating_sub(previous_bytes) as f64 / elapsed
+            };
+
+            stats.received =
+                format_bytes(counters.rx_bytes.saturating_sub(baseline.rx_bytes)).into();
+            stats.sent = format_bytes(counters.tx_bytes.saturating_sub(baseline.tx_bytes)).into();
+            stats.download =
+                format_rate(rate(counters.rx_bytes, previous_counters.rx_bytes)).into();
+            stats.upload = format_rate(rate(counters.tx_bytes, previous_counters.tx_bytes)).into();
+
+            previous = (now, counters);
+        }
+
+        let result = app_weak.upgrade_in_event_loop(move |app| {
+            app.global::<State>().set_session(stats);
+        });
+        if result.is_err() {
+            break; // The event loop is gone
+        }
+    }
+}
+
+/// Format a duration as `HH:MM:SS`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// Format a number of bytes with a decimal unit, e.g. `1.5 MB`.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_second as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create `<sysfs_root>/class/net/<interface>/statistics` with the given counters.
+    fn write_counters(sysfs_root: &Path, interface: &str, rx_bytes: &str, tx_bytes: &str) {
+        let dir = sysfs_root
+            .join("class/net")
+            .join(interface)
+            .join("statistics");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("rx_bytes"), rx_bytes).unwrap();
+        fs::write(dir.join("tx_bytes"), tx_bytes).unwrap();
+    }
+
+    #[test]
+    fn read_counters() {
+        let sysfs_root = tempfile::tempdir().unwrap();
+        write_counters(sysfs_root.path(), DEFAULT_INTERFACE, "1234\n", "5678\n");
+
+        let counters = Counters::read(sysfs_root.path(), DEFAULT_INTERFACE).unwrap();
+        assert_eq!(counters.rx_bytes, 1234);
+        assert_eq!(counters.tx_bytes, 5678);
+    }
+
+    #[test]
+    fn read_missing_or_invalid_counters() {
+        let sysfs_root = tempfile::tempdir().unwrap();
+        assert!(Counters::read(sysfs_root.path(), DEFAULT_INTERFACE).is_err());
+
+        write_counters(sysfs_root.path(), DEFAULT_INTERFACE, "1234\n", "lots\n");
+        let error = Counters::read(sysfs_root.path(), DEFAULT_INTERFACE).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_duration(Duration::from_secs(3725)), "01:02:05");
+        assert_eq!(format_bytes(999), "999 B");
+        assert_eq!(format_bytes(1_500_000), "1.5 MB");
+        assert_eq!(format_rate(2_000.0), "2.0 kB/s");
+    }
+}
@@ -1,33 +1,164 @@
+use std::sync::Mutex;
+
 use image::{GenericImageView, ImageFormat};
+use mullvad_types::states::TunnelState;
+use talpid_types::tunnel::ActionAfterDisconnect;
 use tray_item::{IconSource, TrayItem};
 
-pub fn create_tray_icon() -> anyhow::Result<TrayItem> {
-    // load image and convert to the correct format
-    let tray_icon_image = image::load_from_memory_with_format(
-        include_bytes!("../tray/lock-1.png"),
-        ImageFormat::Png,
-    )?;
-    let (width, height) = tray_icon_image.dimensions();
-    let tray_icon_image = tray_icon_image
-        .into_rgba8()
-        .into_vec()
-        .chunks_exact(4)
-        .flat_map(|rgba| {
-            let &[r, g, b, a] = rgba else { unreachable!() };
-            [a, r, g, b]
+/// The tray icon, if one has been created.
+///
+/// Kept in a static so that it can be updated from wherever the tunnel state is observed.
+static TRAY: Mutex<Option<Tray>> = Mutex::new(None);
+
+struct Tray {
+    item: TrayItem,
+    icon: TrayIcon,
+    /// Whether the icon has a notification badge, e.g. when an update is available.
+    notification: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TrayIcon {
+    /// Traffic is not protected.
+    Unsecured,
+    /// Traffic is blocked, but not yet tunneled.
+    Securing,
+    /// Traffic is tunneled.
+    Secured,
+}
+
+impl TrayIcon {
+    fn png(self, notification: bool) -> &'static [u8] {
+        match (self, notification) {
+            (TrayIcon::Unsecured, false) => include_bytes!("../tray/lock-1.png"),
+            (TrayIcon::Securing, false) => include_bytes!("../tray/lock-10.png"),
+            (TrayIcon::Secured, false) => include_bytes!("../tray/lock-9.png"),
+            (TrayIcon::Unsecured, true) => include_bytes!("../tray/lock-1_notification.png"),
+            (TrayIcon::Securing, true) => include_bytes!("../tray/lock-10_notification.png"),
+            (TrayIcon::Secured, true) => include_bytes!("../tray/lock-9_notification.png"),
+        }
+    }
+
+    fn load(self, notification: bool) -> anyhow::Result<IconSource> {
+        // load image and convert to the correct format
+        let png = self.png(notification);
+        let tray_icon_image = image::load_from_memory_with_format(png, ImageFormat::Png)?;
+        let (width, height) = tray_icon_image.dimensions();
+        let tray_icon_image = tray_icon_image
+            .into_rgba8()
+            .into_vec()
+            .chunks_exact(4)
+            .flat_map(|rgba| {
+                let &[r, g, b, a] = rgba else { unreachable!() };
+                [a, r, g, b]
+            })
+            .collect::<Vec<u8>>();
+
+        Ok(IconSource::Data {
+            data: tray_icon_image,
+            width: width as i32,
+            height: height as i32,
         })
-        .collect::<Vec<u8>>();
+    }
+}
+
+impl From<&TunnelState> for TrayIcon {
+    fn from(tunnel_state: &TunnelState) -> Self {
+        match tunnel_state {
+            TunnelState::Connected { .. } => TrayIcon::Secured,
+            TunnelState::Connecting { .. }
+            | TunnelState::Disconnected {
+                locked_down: true, ..
+            }
+            | TunnelState::Disconnecting(
+                ActionAfterDisconnect::Block | ActionAfterDisconnect::Reconnect,
+            ) => TrayIcon::Securing,
+            TunnelState::Error(error_state) if error_state.is_blocking() => TrayIcon::Securing,
+            TunnelState::Disconnected { .. }
+            | TunnelState::Disconnecting(ActionAfterDisconnect::Nothing)
+            | TunnelState::Error(..) => TrayIcon::Unsecured,
+        }
+    }
+}
 
-    let tray_icon_image = IconSource::Data {
-        data: tray_icon_image,
-        width: width as i32,
-        height: height as i32,
+pub fn create_tray_icon() -> anyhow::Result<()> {
+    let icon = TrayIcon::Unsecured;
+    let item = TrayItem::new("Mullvad VPN (Slint)", icon.load(false)?)?;
+
+    *TRAY.lock().unwrap() = Some(Tray {
+        item,
+        icon,
+        notification: false,
+    });
+
+    Ok(())
+}
+
+/// Add an item to the tray menu. Returns its ID, for [`set_menu_item_label`].
+///
+/// Menu items can't be removed again.
+pub fn add_menu_item(label: &str, on_click: impl Fn() + Send + Sync + 'static) -> Option<u32> {
+    let mut tray = TRAY.lock().unwrap();
+    let tray = tray.as_mut()?;
+
+    match tray.item.inner_mut().add_menu_item_with_id(label, on_click) {
+        Ok(id) => Some(id),
+        Err(e) => {
+            tracing::warn!("Failed to add tray menu item: {e}");
+            None
+        }
+    }
+}
+
+/// Change the label of a tray menu item.
+pub fn set_menu_item_label(id: u32, label: &str) {
+    let mut tray = TRAY.lock().unwrap();
+    let Some(tray) = tray.as_mut() else {
+        return;
     };
 
-    let tray = TrayItem::new("Mullvad VPN (Slint)", tray_icon_image)?;
+    if let Err(e) = tray.item.inner_mut().set_menu_item_label(label, id) {
+        tracing::warn!("Failed to relabel tray menu item: {e}");
+    }
+}
+
+/// Sync the tray icon with the tunnel state.
+pub fn set_tunnel_state(tunnel_state: &TunnelState) {
+    let mut tray = TRAY.lock().unwrap();
+    let Some(tray) = tray.as_mut() else {
+        return;
+    };
+
+    let notification = tray.notification;
+    tray.update(TrayIcon::from(tunnel_state), notification);
+}
+
+/// Show or hide the notification badge on the tray icon.
+pub fn set_notification(notification: bool) {
+    let mut tray = TRAY.lock().unwrap();
+    let Some(tray) = tray.as_mut() else {
+        return;
+    };
+
+    let icon = tray.icon;
+    tray.update(icon, notification);
+}
 
-    // TODO: sync icon with connection state
-    // tray.set_icon(icon)
+impl Tray {
+    fn update(&mut self, icon: TrayIcon, notification: bool) {
+        if icon == self.icon && notification == self.notification {
+            return;
+        }
 
-    Ok(tray)
+        let result = icon
+            .load(notification)
+            .and_then(|source| Ok(self.item.set_icon(source)?));
+        match result {
+            Ok(()) => {
+                self.icon = icon;
+                self.notification = notification;
+            }
+            Err(e) => tracing::warn!("Failed to update tray icon: {e:#}"),
+        }
+    }
 }
@@ -0,0 +1,248 @@
+//! WireGuard settings: port, MTU and the device key.
+
+use std::{ops::RangeInclusive, time::Duration};
+
+use mullvad_types::{
+    constraints::Constraint,
+    relay_constraints::RelaySettings,
+    relay_list::RelayList,
+    settings::Settings,
+    wireguard::{DEFAULT_ROTATION_INTERVAL, PublicKey},
+};
+use slint::{ComponentHandle as _, ModelRc, SharedString, VecModel, Weak};
+
+use crate::{
+    RT, ports,
+    rpc::{self, Rpc},
+    slint_ty::{AppWindow, WireGuard},
+};
+
+/// Ports offered as presets in the UI.
+const PRESET_PORTS: [u16; 2] = [51820, 53];
+
+/// Smallest MTU the daemon accepts. This is the minimum MTU for IPv6.
+const MIN_MTU: u16 = 1280;
+
+/// Largest MTU the daemon accepts.
+const MAX_MTU: u16 = 1420;
+
+/// Install callbacks for the WireGuard settings.
+pub fn setup(app: &AppWindow, rpc: &Rpc) {
+    let wireguard = app.global::<WireGuard>();
+    let preset_ports: VecModel<i32> = PRESET_PORTS.iter().map(|&port| port.into()).collect();
+    wireguard.set_preset_ports(ModelRc::new(preset_ports));
+    wireguard.set_min_mtu(MIN_MTU.into());
+    wireguard.set_max_mtu(MAX_MTU.into());
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        wireguard.on_set_port(move |port| {
+            let port = match ports::parse(&port) {
+                Ok(port) => port,
+                Err(error) => return show_error(&app_weak, error),
+            };
+
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                if let Constraint::Only(port) = port {
+                    let relay_list = rpc.get_relay_locations().await?;
+                    if let Err(error) = ports::check_allowed(port, &allowed_ports(&relay_list)) {
+                        show_error(&app_weak, error);
+                        return Ok(());
+                    }
+                }
+
+                rpc::update_relay_constraints(&mut rpc, |relay_constraints| {
+                    relay_constraints.wireguard_constraints.port = port;
+                    Ok(())
+                })
+                .await?;
+
+                show_error(&app_weak, String::new());
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        wireguard.on_set_mtu(move |mtu| {
+            let mtu = match parse_mtu(&mtu) {
+                Ok(mtu) => mtu,
+                Err(error) => return show_error(&app_weak, error),
+            };
+
+            let app_weak = app_weak.clone();
+            rpc.spawn_with_rpc(async move |mut rpc| {
+                rpc.set_wireguard_mtu(mtu).await?;
+                show_error(&app_weak, String::new());
+                Ok(())
+            });
+        });
+    }
+
+    {
+        let rpc = rpc.clone();
+        let app_weak = app.as_weak();
+        wireguard.on_rotate_key(move || {
+            if let Some(app) = app_weak.upgrade() {
+                let wireguard = app.global::<WireGuard>();
+                wireguard.set_rotating_key(true);
+                wireguard.set_rotation_result("".into());
+            }
+
+            // Not using `spawn_with_rpc`, since the UI must hear about failures to connect too.
+            let rpc = rpc.clone();
+            let app_weak = app_weak.clone();
+            RT.spawn(async move {
+                let result = rpc
+                    .with_rpc(async move |mut rpc| {
+                        rpc.rotate_wireguard_key().await?;
+                        Ok(rpc.get_wireguard_key().await?)
+                    })
+                    .await;
+
+                let message = match result {
+                    Ok(key) => {
+                        update_key(&app_weak, Some(key));
+                        "The key was rotated.".to_owned()
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to rotate WireGuard key: {e:#}");
+                        format!("Failed to rotate the key: {e}")
+                    }
+                };
+
+                let _ = app_weak.upgrade_in_event_loop(move |app| {
+                    let wireguard = app.global::<WireGuard>();
+                    wireguard.set_rotating_key(false);
+                    wireguard.set_rotation_result(message.into());
+                });
+            });
+        });
+    }
+}
+
+/// Sync the WireGuard settings with the daemon settings.
+pub fn update_settings(app: &AppWindow, settings: &Settings) {
+    let wireguard = app.global::<WireGuard>();
+
+    let port = match &settings.relay_settings {
+        RelaySettings::Normal(relay_constraints) => relay_constraints.wireguard_constraints.port,
+        RelaySettings::CustomTunnelEndpoint(_) => Constraint::Any,
+    };
+    let custom_port = matches!(port, Constraint::Only(port) if !PRESET_PORTS.contains(&port));
+    wireguard.set_port(ports::to_text(port));
+    wireguard.set_custom_port(custom_port);
+    wireguard.set_custom_port_text(if custom_port {
+        ports::to_text(port)
+    } else {
+        SharedString::new()
+    });
+
+    let mtu = settings.tunnel_options.wireguard.mtu;
+    wireguard.set_mtu(mtu.map(|mtu| mtu.to_string()).unwrap_or_default().into());
+
+    let rotation_interval = settings
+        .tunnel_options
+        .wireguard
+        .rotation_interval
+        .map(|interval| *interval.as_duration())
+        .unwrap_or(DEFAULT_ROTATION_INTERVAL);
+    wireguard.set_rotation_interval(format_interval(rotation_interval).into());
+}
+
+/// Show this device's WireGuard key. `None` if the device has no key, e.g. when logged out.
+pub fn update_key(app_weak: &Weak<AppWindow>, key: Option<PublicKey>) {
+    let (public_key, created) = match key {
+        Some(key) => (
+            key.key.to_string(),
+            key.created.format("%Y-%m-%d %H:%M UTC").to_string(),
+        ),
+        None => Default::default(),
+    };
+
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        let wireguard = app.global::<WireGuard>();
+        wireguard.set_public_key(public_key.into());
+        wireguard.set_key_created(created.into());
+    });
+}
+
+/// Show which ports the relays accept.
+pub fn update_relay_list(app: &AppWindow, relay_list: &RelayList) {
+    app.global::<WireGuard>()
+        .set_allowed_ports(ports::format_ranges(&allowed_ports(relay_list)));
+}
+
+fn allowed_ports(relay_list: &RelayList) -> Vec<RangeInclusive<u16>> {
+    relay_list.wireguard.port_ranges.clone()
+}
+
+/// Parse an MTU entered by the user. An empty string means the default MTU.
+fn parse_mtu(mtu: &str) -> Result<Option<u16>, String> {
+    match mtu.trim() {
+        "" => Ok(None),
+        mtu => match mtu.parse::<u16>() {
+            Ok(mtu) if (MIN_MTU..=MAX_MTU).contains(&mtu) => Ok(Some(mtu)),
+            _ => Err(format!(
+                "\"{mtu}\" is not a valid MTU. It must be between {MIN_MTU} and {MAX_MTU}."
+            )),
+        },
+    }
+}
+
+/// Format a key rotation interval, e.g. `7 days`.
+fn format_interval(interval: Duration) -> String {
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    let seconds = interval.as_secs();
+    let (count, unit) = if seconds >= DAY && seconds % DAY == 0 {
+        (seconds / DAY, "day")
+    } else {
+        (seconds / HOUR, "hour")
+    };
+    match count {
+        1 => format!("1 {unit}"),
+        count => format!("{count} {unit}s"),
+    }
+}
+
+fn show_error(app_weak: &Weak<AppWindow>, error: String) {
+    let _ = app_weak.upgrade_in_event_loop(move |app| {
+        app.global::<WireGuard>().set_error(error.into());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mtu_bounds() {
+        assert_eq!(parse_mtu(""), Ok(None));
+        assert_eq!(parse_mtu(" 1280 "), Ok(Some(MIN_MTU)));
+        assert_eq!(parse_mtu("1420"), Ok(Some(MAX_MTU)));
+        assert!(parse_mtu("1279").is_err());
+        assert!(parse_mtu("1421").is_err());
+        assert!(parse_mtu("-1").is_err());
+        assert!(parse_mtu("auto").is_err());
+    }
+
+    #[test]
+    fn format_intervals() {
+        assert_eq!(format_interval(Duration::from_secs(24 * 60 * 60)), "1 day");
+        assert_eq!(
+            format_interval(Duration::from_secs(7 * 24 * 60 * 60)),
+            "7 days"
+        );
+        assert_eq!(format_interval(Duration::from_secs(60 * 60)), "1 hour");
+        assert_eq!(
+            format_interval(Duration::from_secs(36 * 60 * 60)),
+            "36 hours"
+        );
+    }
+}